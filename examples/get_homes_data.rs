@@ -1,6 +1,6 @@
 use netatmo_rs::{
     get_homes_data::{GatewayType, Parameters},
-    ClientCredentials, Netatmo, NetatmoClient, Scope,
+    ClientCredentials, Netatmo, NetatmoClient,
 };
 use std::env;
 
@@ -13,12 +13,8 @@ fn main() {
         .expect("Environment variable 'NETATMO_CLIENT_SECRET' is not set.")
         .to_string_lossy()
         .to_string();
-    let username = env::var_os("NETATMO_USERNAME")
-        .expect("Environment variable 'NETATMO_USERNAME' is not set.")
-        .to_string_lossy()
-        .to_string();
-    let password = env::var_os("NETATMO_PASSWORD")
-        .expect("Environment variable 'NETATMO_PASSWORD' is not set.")
+    let refresh_token = env::var_os("NETATMO_REFRESH_TOKEN")
+        .expect("Environment variable 'NETATMO_REFRESH_TOKEN' is not set.")
         .to_string_lossy()
         .to_string();
     let home_id = env::var_os("NETATMO_HOME_ID")
@@ -30,13 +26,12 @@ fn main() {
         client_id: &client_id,
         client_secret: &client_secret,
     };
-    let scopes = vec![Scope::ReadThermostat];
     let m_params = Parameters::new()
         .home_id(&home_id) // to fetch for only one home
         .gateway_types(&[GatewayType::ThermostatValve]); // to fetch for only a specific type of device
 
     let homes_data = NetatmoClient::new(&client_credentials)
-        .authenticate(&username, &password, &scopes)
+        .authenticate(&refresh_token)
         .expect("Failed to authenticate")
         .get_homes_data(&m_params)
         .expect("Failed to get homes data");
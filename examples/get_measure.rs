@@ -1,6 +1,6 @@
 use netatmo_rs::{
     get_measure::{Parameters, Scale, Type},
-    ClientCredentials, Netatmo, NetatmoClient, Scope,
+    ClientCredentials, Netatmo, NetatmoClient,
 };
 use std::env;
 
@@ -13,12 +13,8 @@ fn main() {
         .expect("Environment variable 'NETATMO_CLIENT_SECRET' is not set.")
         .to_string_lossy()
         .to_string();
-    let username = env::var_os("NETATMO_USERNAME")
-        .expect("Environment variable 'NETATMO_USERNAME' is not set.")
-        .to_string_lossy()
-        .to_string();
-    let password = env::var_os("NETATMO_PASSWORD")
-        .expect("Environment variable 'NETATMO_PASSWORD' is not set.")
+    let refresh_token = env::var_os("NETATMO_REFRESH_TOKEN")
+        .expect("Environment variable 'NETATMO_REFRESH_TOKEN' is not set.")
         .to_string_lossy()
         .to_string();
     let device_id = env::var_os("NETATMO_DEVICE_ID")
@@ -30,11 +26,10 @@ fn main() {
         client_id: &client_id,
         client_secret: &client_secret,
     };
-    let scopes = vec![Scope::ReadStation];
     let m_params = Parameters::new(&device_id, Scale::Max, &[Type::Humidity, Type::Temperature, Type::CO2]);
 
     let station_data = NetatmoClient::new(&client_credentials)
-        .authenticate(&username, &password, &scopes)
+        .authenticate(&refresh_token)
         .expect("Failed to authenticate")
         .get_measure(&m_params)
         .expect("Failed to get measure");
@@ -1,6 +1,6 @@
 use netatmo_rs::{
     set_room_thermpoint::{Mode, Parameters},
-    ClientCredentials, Netatmo, NetatmoClient, Scope,
+    ClientCredentials, Netatmo, NetatmoClient,
 };
 use std::env;
 
@@ -13,12 +13,8 @@ fn main() {
         .expect("Environment variable 'NETATMO_CLIENT_SECRET' is not set.")
         .to_string_lossy()
         .to_string();
-    let username = env::var_os("NETATMO_USERNAME")
-        .expect("Environment variable 'NETATMO_USERNAME' is not set.")
-        .to_string_lossy()
-        .to_string();
-    let password = env::var_os("NETATMO_PASSWORD")
-        .expect("Environment variable 'NETATMO_PASSWORD' is not set.")
+    let refresh_token = env::var_os("NETATMO_REFRESH_TOKEN")
+        .expect("Environment variable 'NETATMO_REFRESH_TOKEN' is not set.")
         .to_string_lossy()
         .to_string();
     let home_id = env::var_os("NETATMO_HOME_ID")
@@ -35,10 +31,9 @@ fn main() {
         client_secret: &client_secret,
     };
     let m_params = Parameters::new(&home_id, &room_id, Mode::Home);
-    let scopes = vec![Scope::WriteThermostat];
 
     NetatmoClient::new(&client_credentials)
-        .authenticate(&username, &password, &scopes)
+        .authenticate(&refresh_token)
         .expect("Failed to authenticate")
         .set_room_thermpoint(&m_params)
         .expect("Failed to set home thermpoint");
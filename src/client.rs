@@ -1,28 +1,52 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    ops::Range,
+    path::Path,
+    sync::{Arc, Mutex, RwLock},
+    thread::JoinHandle,
+    time::Duration,
+};
 
-use failure::Fail;
-use log::trace;
-use reqwest::blocking::{Client, Response};
+use log::{trace, warn};
+use reqwest::blocking::{RequestBuilder, Response};
+use reqwest::header::CONTENT_TYPE;
 use reqwest::StatusCode;
 use serde::de::DeserializeOwned;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
-use authenticate::{Scope, Token};
+use authenticate::{Endpoint, Scope, Token, TokenResponse};
+use get_events::Events;
 use get_home_status::HomeStatus;
 use get_homes_data::HomesData;
 use get_measure::Measure;
 use get_station_data::StationData;
+use list_devices::DeviceRef;
+use read_only::ReadOnlyClient;
+use refresh::{OwnedCredentials, RefreshTasks, SharedToken, TokenCallback};
+use transport::Transport;
+pub use transport::{BaseUrls, RateLimitStatus, RetryPolicy, TranscriptEntry};
 
-use crate::errors::{Error, ErrorKind, Result};
+use crate::errors::{Error, ErrorContext, ErrorKind, Result};
 
 pub mod authenticate;
+mod de;
+pub mod device;
+pub mod get_events;
 pub mod get_home_status;
 pub mod get_homes_data;
 pub mod get_measure;
 pub mod get_station_data;
+pub mod list_devices;
+pub mod module_type;
+pub mod read_only;
+mod refresh;
 pub mod set_room_thermpoint;
+pub mod set_state;
+pub mod sync_home_schedule;
+mod transport;
 
 pub trait Netatmo {
+    fn get_events(&self, parameters: &get_events::Parameters) -> Result<Events>;
     fn get_home_status(&self, parameters: &get_home_status::Parameters) -> Result<HomeStatus>;
     fn get_homes_data(&self, parameters: &get_homes_data::Parameters) -> Result<HomesData>;
     fn get_station_data(&self, device_id: &str) -> Result<StationData>;
@@ -32,6 +56,8 @@ pub trait Netatmo {
         &self,
         parameters: &set_room_thermpoint::Parameters,
     ) -> Result<set_room_thermpoint::Response>;
+    fn set_state(&self, parameters: &set_state::Parameters) -> Result<set_state::Response>;
+    fn sync_home_schedule(&self, parameters: &sync_home_schedule::Parameters) -> Result<sync_home_schedule::Response>;
 }
 
 #[derive(Debug)]
@@ -47,73 +73,549 @@ impl<'a> NetatmoClient {
     pub fn new(client_credentials: &'a ClientCredentials) -> UnauthenticatedClient<'a> {
         UnauthenticatedClient {
             client_credentials,
-            http: Client::new(),
+            scopes: Scope::DEFAULT.to_vec(),
+            transport: Transport::new(),
         }
     }
 
+    /// Creates a client from a token obtained elsewhere; such a client cannot refresh its token, cf.
+    /// `AuthenticatedClient::spawn_refresh_task`
     pub fn with_token(token: Token) -> AuthenticatedClient {
-        AuthenticatedClient {
+        AuthenticatedClient::new(token, Transport::new(), None)
+    }
+
+    /// Creates a client from a token stored in a JSON file, cf. `Token::to_file`
+    pub fn from_token_file<P: AsRef<Path>>(path: P) -> Result<AuthenticatedClient> {
+        Token::from_file(path).map(NetatmoClient::with_token)
+    }
+
+    /// Creates a client from a token stored in the OS keyring under `service`, cf. `Token::save_to_keyring`
+    ///
+    /// The stored refresh token is exchanged for a fresh token right away, and since Netatmo rotates refresh tokens,
+    /// the new token is written back to the keyring.
+    #[cfg(feature = "keyring")]
+    pub fn from_keyring(client_credentials: &'a ClientCredentials, service: &str) -> Result<AuthenticatedClient> {
+        let client = NetatmoClient::new(client_credentials);
+        let stored = Token::load_from_keyring(service)?;
+        let token = authenticate::refresh_token(&client, &stored.refresh_token)
+            .map_err(|e| e.context(ErrorKind::AuthenticationFailed))?;
+        token.save_to_keyring(service)?;
+
+        Ok(AuthenticatedClient::new(
             token,
-            http: Client::new(),
-        }
+            client.transport,
+            Some(client_credentials.into()),
+        ))
+    }
+
+    /// Creates a client that doesn't talk to Netatmo, but answers all calls with the responses of a transcript
+    ///
+    /// Each call consumes the first unused entry recorded for the same endpoint, cf. `AuthenticatedClient::record_to`.
+    /// This allows to deterministically reproduce a recorded session, e.g. attached to a bug report.
+    pub fn replay<P: AsRef<Path>>(path: P) -> Result<AuthenticatedClient> {
+        let token = Token {
+            access_token: String::new(),
+            refresh_token: String::new(),
+            scope: Vec::new(),
+            expires_in: 0,
+            expire_in: 0,
+        };
+        Ok(AuthenticatedClient::new(token, Transport::replay_from(path)?, None))
     }
 }
 
 #[derive(Debug)]
 pub struct UnauthenticatedClient<'a> {
     client_credentials: &'a ClientCredentials<'a>,
-    http: Client,
+    /// The scopes the app asks the user to authorize, cf. `scopes`; `Scope::DEFAULT` unless set
+    scopes: Vec<Scope>,
+    transport: Transport,
 }
 
 impl<'a> UnauthenticatedClient<'a> {
+    /// Sets the scopes the app asks for in `authorization_url` and expects to be granted by `exchange_code`
+    ///
+    /// Defaults to `Scope::DEFAULT`. Without any scopes, Netatmo only grants `Scope::ReadStation`.
+    pub fn scopes(self, scopes: &[Scope]) -> Self {
+        UnauthenticatedClient {
+            scopes: scopes.to_vec(),
+            ..self
+        }
+    }
+
+    /// The URL to send the user to for authorizing the app with the scopes set by `scopes`
+    ///
+    /// After authorization, Netatmo redirects to `redirect_uri` with the query parameters `state`, which must match
+    /// the `state` passed here to protect against CSRF, and `code`, which is passed to `exchange_code`.
+    pub fn authorization_url(&self, redirect_uri: &str, state: &str) -> String {
+        authenticate::authorization_url(self, redirect_uri, state)
+    }
+
+    /// Exchanges the `code` of the authorization-code flow for a token, cf. `authorization_url`
+    ///
+    /// Additionally returns the scopes set by `scopes` the user didn't grant, like `authenticate_with_scopes` does.
+    /// `redirect_uri` must be the same as the one passed to `authorization_url`.
+    pub fn exchange_code(self, code: &str, redirect_uri: &str) -> Result<(AuthenticatedClient, Vec<Scope>)> {
+        let token = authenticate::authorization_code(&self, code, redirect_uri)
+            .map_err(|e| e.context(ErrorKind::AuthenticationFailed))?;
+        let missing = token.missing_scopes(&self.scopes);
+        let client = AuthenticatedClient::new(token, self.transport, Some(self.client_credentials.into()));
+
+        Ok((client, missing))
+    }
+
+    /// Exchanges `refresh_token` for an access token
+    ///
+    /// Fails with `ErrorKind::MalformedRefreshToken` without contacting Netatmo if `refresh_token` is obviously
+    /// malformed, cf. `authenticate::validate_refresh_token`. Scopes set by `scopes` but not granted are logged as
+    /// warning only; use `authenticate_with_scopes` to get them.
     pub fn authenticate(self, refresh_token: &'a str) -> Result<AuthenticatedClient> {
+        authenticate::validate_refresh_token(refresh_token)?;
         authenticate::refresh_token(&self, refresh_token)
-            .map(|token| AuthenticatedClient { token, http: self.http })
-            .map_err(|e| e.context(ErrorKind::AuthenticationFailed).into())
+            .map(|token| AuthenticatedClient::new(token, self.transport, Some(self.client_credentials.into())))
+            .map_err(|e| e.context(ErrorKind::AuthenticationFailed))
+    }
+
+    /// Authenticates like `authenticate` and additionally returns the raw response of the token endpoint, e.g. to
+    /// store fields the client doesn't keep
+    pub fn authenticate_with_response(self, refresh_token: &'a str) -> Result<(AuthenticatedClient, TokenResponse)> {
+        authenticate::validate_refresh_token(refresh_token)?;
+        let (token, response) = authenticate::refresh_token_with_response(&self, refresh_token)
+            .map_err(|e| e.context(ErrorKind::AuthenticationFailed))?;
+        let client = AuthenticatedClient::new(token, self.transport, Some(self.client_credentials.into()));
+
+        Ok((client, response))
+    }
+
+    /// Authenticates like `authenticate` and additionally returns the scopes of `expected` the token lacks
+    ///
+    /// API calls requiring a missing scope will fail, so the user should be asked to authorize the app again for all
+    /// expected scopes. Missing scopes are logged as warning, too.
+    pub fn authenticate_with_scopes(
+        self,
+        refresh_token: &'a str,
+        expected: &[Scope],
+    ) -> Result<(AuthenticatedClient, Vec<Scope>)> {
+        let client = self.scopes(expected).authenticate(refresh_token)?;
+        let missing = client.token().missing_scopes(expected);
+
+        Ok((client, missing))
+    }
+
+    /// Authenticates like `authenticate`, but returns a client limited to the reading API calls
+    pub fn authenticate_read_only(self, refresh_token: &'a str) -> Result<ReadOnlyClient> {
+        self.authenticate(refresh_token).map(ReadOnlyClient::from)
+    }
+
+    /// Sets the User-Agent header sent with every request, cf. `AuthenticatedClient::with_user_agent`
+    pub fn with_user_agent(self, user_agent: &str) -> Self {
+        UnauthenticatedClient {
+            transport: self.transport.user_agent(user_agent),
+            ..self
+        }
+    }
+
+    /// Sets how transiently failing requests are retried, cf. `AuthenticatedClient::with_retry_policy`
+    ///
+    /// This covers the token requests, so that authenticating survives a brief outage of Netatmo.
+    pub fn with_retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        UnauthenticatedClient {
+            transport: self.transport.retry_policy(retry_policy),
+            ..self
+        }
     }
 
-    pub(crate) fn call<T>(&self, name: &'static str, url: &str, params: &HashMap<&str, &str>) -> Result<T>
+    /// Sends the requests to other hosts than Netatmo's, cf. `AuthenticatedClient::with_base_urls`
+    pub fn with_base_urls(self, base_urls: BaseUrls) -> Self {
+        UnauthenticatedClient {
+            transport: self.transport.with_base_urls(base_urls),
+            ..self
+        }
+    }
+
+    /// Records all requests and responses to a transcript at `path`, cf. `AuthenticatedClient::record_to`
+    pub fn record_to<P: AsRef<Path>>(self, path: P) -> Result<Self> {
+        Ok(UnauthenticatedClient {
+            transport: self.transport.record_to(path)?,
+            ..self
+        })
+    }
+
+    /// Calls the OAuth endpoint at `path` on the OAuth host, cf. `BaseUrls`
+    pub(crate) fn call<T>(&self, name: &'static str, path: &str, params: &HashMap<&str, &str>) -> Result<T>
     where
         T: DeserializeOwned,
     {
-        api_call(name, &self.http, url, params)
+        let url = self.transport.base_urls().oauth_url(path);
+        api_call(name, &self.transport, &url, params)
     }
 }
 
+/// A client holding a valid access token
+///
+/// All calls share the same HTTP client and thus its connection pool, so consecutive calls to the same host reuse
+/// established connections instead of opening a new one per call.
 pub struct AuthenticatedClient {
-    token: Token,
-    http: Client,
+    /// Shared with the refresh tasks, cf. `spawn_refresh_task`
+    token: Arc<RwLock<SharedToken>>,
+    transport: Transport,
+    /// The credentials the token has been obtained with; required to refresh the token
+    credentials: Option<OwnedCredentials>,
+    on_token_refresh: Option<TokenCallback>,
+    refresh_tasks: RefreshTasks,
+    /// The last status of each home polled by `poll_home_status_changes`
+    polled_homes: Mutex<HashMap<String, get_home_status::Home>>,
 }
 
 impl AuthenticatedClient {
-    pub fn token(&self) -> &Token {
-        &self.token
+    fn new(token: Token, transport: Transport, credentials: Option<OwnedCredentials>) -> Self {
+        AuthenticatedClient {
+            token: SharedToken::new(token),
+            transport,
+            credentials,
+            on_token_refresh: None,
+            refresh_tasks: RefreshTasks::default(),
+            polled_homes: Mutex::default(),
+        }
+    }
+
+    /// The scopes granting access to `endpoint`, e.g. to request only the scopes of the features a user enabled,
+    /// cf. `Endpoint::scopes`
+    pub fn scopes_required_for(endpoint: Endpoint) -> &'static [Scope] {
+        endpoint.scopes()
     }
 
-    pub(crate) fn call<'a, T>(&'a self, name: &'static str, url: &str, params: &mut HashMap<&str, &'a str>) -> Result<T>
+    /// The current token, which changes when a refresh task refreshes it, cf. `spawn_refresh_task`
+    pub fn token(&self) -> Token {
+        self.token.read().expect("token lock poisoned").token.clone()
+    }
+
+    /// Calls `on_token_refresh` with every token refreshed by a refresh task, e.g. to persist it, since Netatmo
+    /// rotates refresh tokens
+    pub fn on_token_refresh<F>(self, on_token_refresh: F) -> Self
     where
+        F: Fn(&Token) + Send + Sync + 'static,
+    {
+        AuthenticatedClient {
+            on_token_refresh: Some(Arc::new(on_token_refresh)),
+            ..self
+        }
+    }
+
+    /// Spawns a thread refreshing the token `margin` before it expires, so calls never wait for a refresh
+    ///
+    /// Refreshed tokens are passed to the callback set by `on_token_refresh`; failed refreshes are logged and retried
+    /// after a minute. The thread stops when this client is dropped. Fails for clients without client credentials,
+    /// i.e. created by `NetatmoClient::with_token`, `NetatmoClient::from_token_file`, or `NetatmoClient::replay`.
+    pub fn spawn_refresh_task(&self, margin: Duration) -> Result<JoinHandle<()>> {
+        let credentials = self.credentials.clone().ok_or(ErrorKind::MissingClientCredentials)?;
+        let (task, handle) = refresh::spawn(
+            Arc::clone(&self.token),
+            credentials,
+            self.transport.detached(),
+            self.on_token_refresh.clone(),
+            margin,
+        );
+        self.refresh_tasks.push(task);
+
+        Ok(handle)
+    }
+
+    /// Stops the refresh tasks spawned by `spawn_refresh_task` and waits up to a second for them to finish
+    ///
+    /// Returns whether all tasks finished in time; a task in the middle of a refresh finishes once the refresh
+    /// completes. Dropping the client stops the tasks like this, too.
+    pub fn shutdown(&self) -> bool {
+        self.refresh_tasks.stop()
+    }
+
+    fn access_token(&self) -> String {
+        self.token
+            .read()
+            .expect("token lock poisoned")
+            .token
+            .access_token
+            .clone()
+    }
+
+    /// Fetches the measures of a weather station and all its modules, cf. `get_measure::get_measure_all_modules`
+    pub fn get_measure_all_modules(
+        &self,
+        device: &get_station_data::Device,
+        parameters: &get_measure::Parameters,
+    ) -> get_measure::StationMeasures {
+        get_measure::get_measure_all_modules(self, device, parameters)
+    }
+
+    /// Polls the newest raw measures of a module every `interval` and calls `on_data` with each new reading, e.g. for a
+    /// live dashboard
+    ///
+    /// Polling starts with the readings of the last `interval`, and readings already passed to `on_data` are skipped.
+    /// Blocks until `on_data` returns `false` or a call fails.
+    pub fn measure_stream<F>(
+        &self,
+        device_id: &str,
+        module_id: &str,
+        measurement_type: get_measure::Type,
+        interval: Duration,
+        on_data: F,
+    ) -> Result<()>
+    where
+        F: FnMut(get_measure::Reading) -> bool,
+    {
+        get_measure::measure_stream(self, device_id, module_id, measurement_type, interval, on_data)
+    }
+
+    /// Fetches the measures of a module within `range`, a range of seconds since the epoch, with a fine scale for
+    /// recent readings and coarser scales for older ones, e.g. for a chart of a long range
+    ///
+    /// The readings of the last two days of `range` have a scale of 30 minutes, up to 30 days of 3 hours, and older
+    /// ones of a day. Scales change at the boundaries of the station's local windows, so no aggregation window is
+    /// covered twice and cumulative types like rain sum up consistently, also for stations outside of UTC.
+    pub fn history_adaptive(
+        &self,
+        device_id: &str,
+        module_id: &str,
+        measurement_type: get_measure::Type,
+        range: Range<usize>,
+    ) -> Result<Vec<get_measure::Reading>> {
+        get_measure::history_adaptive(self, device_id, module_id, measurement_type, range)
+    }
+
+    /// A best-effort estimate of the remaining rate limit budget, e.g. to defer optional bulk jobs
+    ///
+    /// The estimate is based on the requests this client sent within the last hour and on rate limited responses, so
+    /// requests of other clients using the same account are not accounted for.
+    pub fn rate_limit_status(&self) -> RateLimitStatus {
+        self.transport.rate_limit_status()
+    }
+
+    /// Limits this client to the reading API calls, cf. `ReadOnlyClient`
+    pub fn into_read_only(self) -> ReadOnlyClient {
+        ReadOnlyClient::from(self)
+    }
+
+    /// Sets the User-Agent header sent with every request, e.g. "myapp/1.2 (netatmo-rs)"
+    ///
+    /// Defaults to "netatmo-rs/" followed by the version of this crate.
+    pub fn with_user_agent(self, user_agent: &str) -> Self {
+        AuthenticatedClient {
+            transport: self.transport.user_agent(user_agent),
+            ..self
+        }
+    }
+
+    /// Sets how requests failing because of a network or server error are retried
+    ///
+    /// Defaults to `RetryPolicy::default()`; use `RetryPolicy::none()` to fail on the first error. Calls changing the
+    /// state of devices are never retried, cf. `RetryPolicy`.
+    pub fn with_retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        AuthenticatedClient {
+            transport: self.transport.retry_policy(retry_policy),
+            ..self
+        }
+    }
+
+    /// Sends the requests to other hosts than Netatmo's, e.g. a proxy or a test server
+    ///
+    /// The OAuth host is used by refresh tasks, cf. `spawn_refresh_task`.
+    pub fn with_base_urls(self, base_urls: BaseUrls) -> Self {
+        AuthenticatedClient {
+            transport: self.transport.with_base_urls(base_urls),
+            ..self
+        }
+    }
+
+    /// Sends `If-None-Match` with the ETag of the previous response to an identical request, if Netatmo returned one
+    ///
+    /// When Netatmo reports the data as not modified, the previous response is used instead of transferring it again,
+    /// which saves bandwidth for frequent polling. Endpoints not supporting ETags are unaffected. The previous
+    /// responses are kept in memory, one per distinct request.
+    pub fn with_conditional_requests(self) -> Self {
+        AuthenticatedClient {
+            transport: self.transport.conditional_requests(),
+            ..self
+        }
+    }
+
+    /// Records all requests and responses to a transcript at `path` which can be replayed by `NetatmoClient::replay`
+    ///
+    /// Credentials are redacted from the transcript.
+    pub fn record_to<P: AsRef<Path>>(self, path: P) -> Result<Self> {
+        Ok(AuthenticatedClient {
+            transport: self.transport.record_to(path)?,
+            ..self
+        })
+    }
+
+    /// Lists all weather stations, home coaches, and energy devices including their modules accessible with this
+    /// client's token
+    ///
+    /// Endpoints the token lacks the scope for are skipped.
+    pub fn list_devices(&self) -> Result<Vec<DeviceRef>> {
+        list_devices::list_devices(self)
+    }
+
+    /// Fetches the events of `home_id` that happened after the event `last_event_id`, oldest first
+    ///
+    /// This allows to resumably poll for new events: pass the id of the last returned event on the next call. Fails
+    /// with `ErrorKind::EventNotFound` if Netatmo no longer returns that event, as then events after it may be missing.
+    pub fn new_events_since(&self, home_id: &str, last_event_id: &str) -> Result<Vec<get_events::Event>> {
+        get_events::new_events_since(self, home_id, last_event_id)
+    }
+
+    /// Fetches the status of `home_id` and returns the rooms whose setpoints, setpoint modes, or measured temperature
+    /// changed since the previous call for this home, cf. `get_home_status::Home::room_changes`
+    ///
+    /// Netatmo has no incremental status, so this fetches the full status and compares it to the one of the previous
+    /// call kept by this client. The first call returns all rooms.
+    pub fn poll_home_status_changes(&self, home_id: &str) -> Result<Vec<get_home_status::RoomChange>> {
+        let home_status = self.get_home_status(&get_home_status::Parameters::new().home_id(home_id))?;
+        let current = home_status.body.home;
+        let mut polled_homes = self.polled_homes.lock().expect("polled homes lock poisoned");
+        let changes = match polled_homes.get(home_id) {
+            Some(previous) => current.room_changes(previous),
+            None => current.room_changes(&get_home_status::Home::default()),
+        };
+        polled_homes.insert(home_id.to_string(), current);
+
+        Ok(changes)
+    }
+
+    /// Calls the API endpoint at `path` on the API host, cf. `BaseUrls`
+    pub(crate) fn call<T>(&self, name: &'static str, path: &str, params: &HashMap<&str, &str>) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let access_token = self.access_token();
+        let mut params = params.clone();
+        params.insert("access_token", &access_token);
+        let url = self.transport.base_urls().api_url(path);
+        api_call(name, &self.transport, &url, &params)
+    }
+
+    /// Calls the API endpoint at `path` like `call`, but without retries as the call changes state, cf. `RetryPolicy`
+    pub(crate) fn call_write<T>(&self, name: &'static str, path: &str, params: &HashMap<&str, &str>) -> Result<T>
+    where
+        T: DeserializeOwned,
+    {
+        let access_token = self.access_token();
+        let mut params = params.clone();
+        params.insert("access_token", &access_token);
+        let request = self
+            .transport
+            .post(&self.transport.base_urls().api_url(path))
+            .form(&params);
+        let res = self
+            .transport
+            .send_write(name, request, &params)?
+            .general_err_handler(name, StatusCode::OK)?;
+
+        parse_response(res)
+    }
+
+    /// Posts `body` as JSON to the API endpoint at `path`; the call is not retried as it changes state, cf.
+    /// `RetryPolicy`
+    pub(crate) fn call_json<B, T>(&self, name: &'static str, path: &str, body: &B) -> Result<T>
+    where
+        B: Serialize,
         T: DeserializeOwned,
     {
-        params.insert("access_token", &self.token.access_token);
-        api_call(name, &self.http, url, params)
+        let body = serde_json::to_string(body).map_err(|e| e.context(ErrorKind::JsonSerializationFailed))?;
+        let request = self
+            .transport
+            .post(&self.transport.base_urls().api_url(path))
+            .bearer_auth(self.access_token())
+            .header(CONTENT_TYPE, "application/json")
+            .body(body);
+        let res = self
+            .transport
+            .send_write(name, request, &HashMap::default())?
+            .general_err_handler(name, StatusCode::OK)?;
+
+        parse_response(res)
+    }
+
+    /// Fetches binary data like an image from Netatmo's API, authenticated by the access token
+    pub(crate) fn call_bytes(&self, name: &'static str, path: &str, params: &HashMap<&str, &str>) -> Result<Vec<u8>> {
+        let url = self.transport.base_urls().api_url(path);
+        let request = self.transport.get(&url).query(params).bearer_auth(self.access_token());
+        read_bytes(name, &self.transport, request, params)
+    }
+
+    /// Fetches binary data from a URL that must not receive the access token, e.g. a pre-signed URL of another host
+    ///
+    /// The transcript records the URL without its query, which carries the signature of pre-signed URLs.
+    pub(crate) fn fetch_bytes(&self, name: &'static str, url: &str) -> Result<Vec<u8>> {
+        let request = self.transport.get(url);
+        let mut params = HashMap::default();
+        params.insert("url", url.split('?').next().unwrap_or_default());
+        read_bytes(name, &self.transport, request, &params)
     }
 }
 
-fn api_call<T>(name: &'static str, http: &Client, url: &str, params: &HashMap<&str, &str>) -> Result<T>
+fn read_bytes(
+    name: &'static str,
+    transport: &Transport,
+    request: RequestBuilder,
+    params: &HashMap<&str, &str>,
+) -> Result<Vec<u8>> {
+    let res = transport
+        .send(name, request, params)?
+        .general_err_handler(name, StatusCode::OK)?;
+    let request_id = transport::request_id(&res);
+    res.bytes()
+        .map(|x| x.to_vec())
+        .map_err(|e| e.context(ErrorKind::FailedToReadResponse).with_request_id(request_id))
+}
+
+fn api_call<T>(name: &'static str, transport: &Transport, url: &str, params: &HashMap<&str, &str>) -> Result<T>
 where
     T: DeserializeOwned,
 {
-    let res = http
-        .post(url)
-        .form(&params)
-        .send()
-        .map_err(|e| e.context(ErrorKind::FailedToSendRequest))?
+    let request = transport.post(url).form(&params);
+    let res = transport
+        .send_cached(name, request, params)?
         .general_err_handler(name, StatusCode::OK)?;
 
+    parse_response(res)
+}
+
+fn parse_response<T>(res: Response) -> Result<T>
+where
+    T: DeserializeOwned,
+{
     let status = res.status();
-    let body = res.text().map_err(|e| e.context(ErrorKind::FailedToReadResponse))?;
-    trace!("Sucessful ({:?}) repsone: '{}'", status, body);
-    serde_json::from_str::<T>(&body).map_err(|e| e.context(ErrorKind::JsonDeserializationFailed).into())
+    let request_id = transport::request_id(&res);
+    let body = res.text().map_err(|e| {
+        e.context(ErrorKind::FailedToReadResponse)
+            .with_request_id(request_id.clone())
+    })?;
+    trace!(
+        "Sucessful ({:?}) repsone to request {}: '{}'",
+        status,
+        request_id.as_deref().unwrap_or("-"),
+        body
+    );
+    from_json(body).map_err(|e| e.with_request_id(request_id))
+}
+
+#[cfg(not(feature = "simd-json"))]
+fn from_json<T>(body: String) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    serde_json::from_str::<T>(&body).map_err(|e| e.context(ErrorKind::JsonDeserializationFailed))
+}
+
+/// simd-json parses in place and thus needs the body as mutable bytes
+#[cfg(feature = "simd-json")]
+fn from_json<T>(body: String) -> Result<T>
+where
+    T: DeserializeOwned,
+{
+    let mut bytes = body.into_bytes();
+    simd_json::serde::from_slice::<T>(&mut bytes).map_err(|e| e.context(ErrorKind::JsonDeserializationFailed))
 }
 
 pub(crate) trait GeneralErrHandler {
@@ -122,6 +624,9 @@ pub(crate) trait GeneralErrHandler {
     fn general_err_handler(self, name: &'static str, expected_status: StatusCode) -> Result<Self::T>;
 }
 
+/// Netatmo's API error code for a device or module id that doesn't exist or isn't accessible with the token
+const DEVICE_NOT_FOUND: isize = 9;
+
 #[derive(Debug, Deserialize)]
 struct ApiError {
     #[serde(rename = "error")]
@@ -138,65 +643,384 @@ impl GeneralErrHandler for Response {
     type T = Response;
 
     fn general_err_handler(self, name: &'static str, expected_status: StatusCode) -> Result<Self> {
-        match self.status() {
-            code if code == expected_status => Ok(self),
-            code @ StatusCode::BAD_REQUEST
-            | code @ StatusCode::UNAUTHORIZED
-            | code @ StatusCode::FORBIDDEN
-            | code @ StatusCode::NOT_FOUND
-            | code @ StatusCode::NOT_ACCEPTABLE
-            | code @ StatusCode::INTERNAL_SERVER_ERROR => {
-                let body = self.text().map_err(|e| {
-                    e.context(ErrorKind::UnknownApiCallFailure {
-                        name,
-                        status_code: code.as_u16(),
-                    })
-                })?;
-                let err: ApiError = serde_json::from_str(&body).map_err(|e| {
-                    e.context(ErrorKind::UnknownApiCallFailure {
-                        name,
-                        status_code: code.as_u16(),
-                    })
-                })?;
-                Err(Error::from(ErrorKind::ApiCallFailed {
+        let request_id = transport::request_id(&self);
+        check_status(self, name, expected_status).map_err(|e| e.with_request_id(request_id))
+    }
+}
+
+fn check_status(res: Response, name: &'static str, expected_status: StatusCode) -> Result<Response> {
+    match res.status() {
+        code if code == expected_status => Ok(res),
+        StatusCode::TOO_MANY_REQUESTS => {
+            let retry_after = transport::retry_after(&res);
+            Err(Error::from(ErrorKind::RateLimited { name, retry_after }))
+        }
+        code @ StatusCode::BAD_REQUEST
+        | code @ StatusCode::UNAUTHORIZED
+        | code @ StatusCode::FORBIDDEN
+        | code @ StatusCode::NOT_FOUND
+        | code @ StatusCode::NOT_ACCEPTABLE
+        | code @ StatusCode::INTERNAL_SERVER_ERROR => {
+            let body = res.text().map_err(|e| {
+                e.context(ErrorKind::UnknownApiCallFailure {
+                    name,
+                    status_code: code.as_u16(),
+                })
+            })?;
+            let err: ApiError = serde_json::from_str(&body).map_err(|e| {
+                e.context(ErrorKind::UnknownApiCallFailure {
                     name,
-                    code: err.details.code,
-                    msg: err.details.message,
-                }))
+                    status_code: code.as_u16(),
+                })
+            })?;
+            if err.details.code == DEVICE_NOT_FOUND {
+                return Err(Error::from(ErrorKind::DeviceNotFound { name }));
             }
-            code => Err(Error::from(ErrorKind::UnknownApiCallFailure {
+            Err(Error::from(ErrorKind::ApiCallFailed {
                 name,
-                status_code: code.as_u16(),
-            })),
+                code: err.details.code,
+                msg: err.details.message,
+            }))
         }
+        code => Err(Error::from(ErrorKind::UnknownApiCallFailure {
+            name,
+            status_code: code.as_u16(),
+        })),
     }
 }
 
+/// Netatmo answers some calls with empty data instead of an error if the token lacks the scope to read the data
+///
+/// Returns a hint naming the scopes if `is_empty` and the token has none of `scopes`. Tokens with unknown scopes, e.g.
+/// of clients created by `NetatmoClient::with_token`, and calls without known scopes, e.g. `set_state`, never get a
+/// hint.
+fn missing_scope_hint(name: &str, token: &Token, is_empty: bool, scopes: &[Scope]) -> Option<String> {
+    if !is_empty || token.scope.is_empty() || scopes.is_empty() || scopes.iter().any(|x| token.scope.contains(x)) {
+        return None;
+    }
+    let scopes = scopes.iter().map(|x| x.to_scope_str()).collect::<Vec<_>>().join(" or ");
+    Some(format!("API call '{}' returned no data; is {} granted?", name, scopes))
+}
+
+impl AuthenticatedClient {
+    fn warn_if_missing_scope(&self, name: &str, is_empty: bool, scopes: &[Scope]) {
+        let token = self.token.read().expect("token lock poisoned");
+        if let Some(hint) = missing_scope_hint(name, &token.token, is_empty, scopes) {
+            warn!("{}", hint);
+        }
+    }
+}
+
+/// Calls returning no data without one of the respective scopes log a warning, cf. `missing_scope_hint`
 impl Netatmo for AuthenticatedClient {
+    fn get_events(&self, parameters: &get_events::Parameters) -> Result<Events> {
+        let events = get_events::get_events(self, parameters)?;
+        self.warn_if_missing_scope(
+            "get_events",
+            events.body.home.events.is_empty(),
+            Endpoint::GetEvents.scopes(),
+        );
+
+        Ok(events)
+    }
+
     fn get_homes_data(&self, parameters: &get_homes_data::Parameters) -> Result<HomesData> {
-        get_homes_data::get_homes_data(&self, parameters)
+        get_homes_data::get_homes_data(self, parameters)
     }
 
     fn get_home_status(&self, parameters: &get_home_status::Parameters) -> Result<HomeStatus> {
-        get_home_status::get_home_status(&self, parameters)
+        let home_status = get_home_status::get_home_status(self, parameters)?;
+        self.warn_if_missing_scope(
+            "get_home_status",
+            home_status.body.home.modules.is_empty(),
+            Endpoint::GetHomeStatus.scopes(),
+        );
+
+        Ok(home_status)
     }
 
     fn get_station_data(&self, device_id: &str) -> Result<StationData> {
-        get_station_data::get_station_data(&self, device_id)
+        let station_data = get_station_data::get_station_data(self, Some(device_id))?;
+        self.warn_if_missing_scope(
+            "get_station_data",
+            station_data.body.devices.is_empty(),
+            Endpoint::GetStationData.scopes(),
+        );
+
+        Ok(station_data)
     }
 
     fn get_homecoachs_data(&self, device_id: &str) -> Result<StationData> {
-        get_station_data::get_homecoachs_data(self, device_id)
+        let station_data = get_station_data::get_homecoachs_data(self, Some(device_id))?;
+        self.warn_if_missing_scope(
+            "get_homecoachs_data",
+            station_data.body.devices.is_empty(),
+            Endpoint::GetHomecoachsData.scopes(),
+        );
+
+        Ok(station_data)
     }
 
     fn get_measure(&self, parameters: &get_measure::Parameters) -> Result<Measure> {
-        get_measure::get_measure(&self, parameters)
+        get_measure::get_measure(self, parameters)
     }
 
     fn set_room_thermpoint(
         &self,
         parameters: &set_room_thermpoint::Parameters,
     ) -> Result<set_room_thermpoint::Response> {
-        set_room_thermpoint::set_room_thermpoint(&self, parameters)
+        set_room_thermpoint::set_room_thermpoint(self, parameters)
+    }
+
+    fn set_state(&self, parameters: &set_state::Parameters) -> Result<set_state::Response> {
+        set_state::set_state(self, parameters)
+    }
+
+    fn sync_home_schedule(&self, parameters: &sync_home_schedule::Parameters) -> Result<sync_home_schedule::Response> {
+        sync_home_schedule::sync_home_schedule(self, parameters)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+    use std::time::SystemTime;
+
+    use super::*;
+
+    fn response(status: u16, headers: &[(&str, &str)], body: &str) -> Response {
+        let mut builder = http::Response::builder().status(status);
+        for (k, v) in headers {
+            builder = builder.header(*k, *v);
+        }
+        Response::from(builder.body(body.to_string()).unwrap())
+    }
+
+    mod general_err_handler {
+        use super::*;
+
+        #[test]
+        fn rate_limited_with_retry_after() {
+            let res = response(429, &[("Retry-After", "120")], "");
+
+            let res = res.general_err_handler("get_measure", StatusCode::OK);
+
+            assert_that(&res.map_err(|e| e.kind().clone())).is_err_containing(ErrorKind::RateLimited {
+                name: "get_measure",
+                retry_after: Some(120),
+            });
+        }
+
+        #[test]
+        fn rate_limited_with_retry_after_date() {
+            let past = response(429, &[("Retry-After", "Wed, 21 Oct 2015 07:28:00 GMT")], "");
+            let future = httpdate::fmt_http_date(SystemTime::now() + Duration::from_secs(3600));
+            let future = response(429, &[("Retry-After", &future)], "");
+
+            let past = past.general_err_handler("get_measure", StatusCode::OK);
+            let future = future.general_err_handler("get_measure", StatusCode::OK);
+
+            assert_that(&past.map_err(|e| e.kind().clone())).is_err_containing(ErrorKind::RateLimited {
+                name: "get_measure",
+                retry_after: Some(0),
+            });
+            let retry_after = match future.map_err(|e| e.kind().clone()) {
+                Err(ErrorKind::RateLimited { retry_after, .. }) => retry_after,
+                _ => None,
+            };
+            assert_that(&retry_after).is_some().is_greater_than(3500);
+        }
+
+        #[test]
+        fn rate_limited_with_malformed_retry_after() {
+            let res = response(429, &[("Retry-After", "soon")], "");
+
+            let res = res.general_err_handler("get_measure", StatusCode::OK);
+
+            assert_that(&res.map_err(|e| e.kind().clone())).is_err_containing(ErrorKind::RateLimited {
+                name: "get_measure",
+                retry_after: None,
+            });
+        }
+
+        #[test]
+        fn rate_limited_without_retry_after() {
+            let res = response(429, &[], "");
+
+            let res = res.general_err_handler("get_measure", StatusCode::OK);
+
+            assert_that(&res.map_err(|e| e.kind().clone())).is_err_containing(ErrorKind::RateLimited {
+                name: "get_measure",
+                retry_after: None,
+            });
+        }
+
+        #[test]
+        fn device_not_found() {
+            let res = response(400, &[], r#"{"error": {"code": 9, "message": "Device not found"}}"#);
+
+            let res = res.general_err_handler("get_measure", StatusCode::OK);
+
+            assert_that(&res.map_err(|e| e.kind().clone()))
+                .is_err_containing(ErrorKind::DeviceNotFound { name: "get_measure" });
+        }
+
+        #[test]
+        fn api_call_failed() {
+            let res = response(403, &[], r#"{"error": {"code": 13, "message": "Operation forbidden"}}"#);
+
+            let res = res.general_err_handler("get_measure", StatusCode::OK);
+
+            assert_that(&res.map_err(|e| e.kind().clone())).is_err_containing(ErrorKind::ApiCallFailed {
+                name: "get_measure",
+                code: 13,
+                msg: "Operation forbidden".to_string(),
+            });
+        }
+    }
+
+    mod missing_scope_hint {
+        use super::*;
+
+        fn token(scope: Vec<Scope>) -> Token {
+            Token {
+                access_token: "access|1".to_string(),
+                refresh_token: "refresh|1".to_string(),
+                scope,
+                expires_in: 10800,
+                expire_in: 10800,
+            }
+        }
+
+        #[test]
+        fn empty_without_scope() {
+            let hint = missing_scope_hint(
+                "get_events",
+                &token(vec![Scope::ReadStation]),
+                true,
+                &[Scope::ReadCamera, Scope::ReadPresence],
+            );
+
+            assert_that(&hint).is_equal_to(Some(
+                "API call 'get_events' returned no data; is read_camera or read_presence granted?".to_string(),
+            ));
+        }
+
+        #[test]
+        fn no_hint_with_scope_data_or_unknown_scopes() {
+            let scopes = [Scope::ReadCamera, Scope::ReadPresence];
+
+            let granted = missing_scope_hint("get_events", &token(vec![Scope::ReadPresence]), true, &scopes);
+            let not_empty = missing_scope_hint("get_events", &token(vec![Scope::ReadStation]), false, &scopes);
+            let unknown = missing_scope_hint("get_events", &token(vec![]), true, &scopes);
+
+            assert_that(&granted).is_none();
+            assert_that(&not_empty).is_none();
+            assert_that(&unknown).is_none();
+        }
+
+        #[test]
+        fn no_hint_for_shutter_write() {
+            let scopes = AuthenticatedClient::scopes_required_for(Endpoint::SetState);
+
+            let hint = missing_scope_hint("set_state", &token(vec![Scope::ReadStation]), true, scopes);
+
+            assert_that(&scopes.contains(&Scope::WriteThermostat)).is_false();
+            assert_that(&scopes.contains(&Scope::WriteCamera)).is_false();
+            assert_that(&hint).is_none();
+        }
+    }
+
+    mod parse_response {
+        use super::*;
+
+        #[test]
+        fn parse_measure() {
+            let res = response(
+                200,
+                &[],
+                r#"{"status": "ok", "time_exec": 0.1, "body": {"100": [20.1, null], "200": [20.2, 44]}}"#,
+            );
+
+            let measure: Result<Measure> = parse_response(res);
+
+            assert_that(&measure).is_ok();
+            assert_that(&measure.unwrap().into_readings()).has_length(2);
+        }
+
+        #[test]
+        fn invalid_json() {
+            let res = response(200, &[], r#"{"status": "ok""#);
+
+            let measure: Result<Measure> = parse_response(res);
+
+            assert_that(&measure.map_err(|e| e.kind().clone())).is_err_containing(ErrorKind::JsonDeserializationFailed);
+        }
+    }
+
+    mod user_agent {
+        use super::*;
+        use std::{
+            io::{BufRead, BufReader, Write},
+            net::TcpListener,
+            thread,
+        };
+
+        /// The User-Agent header of the token request `client` sends to a local server
+        fn sent_user_agent(client: UnauthenticatedClient) -> Option<String> {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+            let server = thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut user_agent = None;
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    let line = line.trim_end();
+                    if line.is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(": ") {
+                        if name.eq_ignore_ascii_case("user-agent") {
+                            user_agent = Some(value.to_string());
+                        }
+                    }
+                }
+                stream
+                    .write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 2\r\nConnection: close\r\n\r\n{}")
+                    .unwrap();
+                user_agent
+            });
+
+            let res = client
+                .with_retry_policy(RetryPolicy::none())
+                .with_base_urls(BaseUrls::single(&url).unwrap())
+                .authenticate("refresh|1");
+
+            assert_that(&res.is_err()).is_true();
+            server.join().unwrap()
+        }
+
+        fn client() -> UnauthenticatedClient<'static> {
+            NetatmoClient::new(&ClientCredentials {
+                client_id: "client",
+                client_secret: "secret",
+            })
+        }
+
+        #[test]
+        fn default() {
+            let user_agent = sent_user_agent(client());
+
+            assert_that(&user_agent).is_equal_to(Some(format!("netatmo-rs/{}", env!("CARGO_PKG_VERSION"))));
+        }
+
+        #[test]
+        fn custom() {
+            let user_agent = sent_user_agent(client().with_user_agent("myapp/1.2 (netatmo-rs)"));
+
+            assert_that(&user_agent).is_equal_to(Some("myapp/1.2 (netatmo-rs)".to_string()));
+        }
     }
 }
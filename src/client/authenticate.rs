@@ -1,9 +1,13 @@
-use crate::{client::UnauthenticatedClient, errors::Result};
+use crate::{
+    client::UnauthenticatedClient,
+    errors::{ErrorContext, ErrorKind, Result},
+};
 
+use log::warn;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fmt};
+use std::{collections::HashMap, fmt, fs, path::Path};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Token {
     pub access_token: String,
     pub refresh_token: String,
@@ -12,6 +16,116 @@ pub struct Token {
     pub expire_in: u64,
 }
 
+impl Token {
+    /// Reads a token from a JSON file as written by `Token::to_file`
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Token> {
+        let json = fs::read_to_string(path).map_err(|e| e.context(ErrorKind::TokenFileAccessFailed))?;
+        serde_json::from_str(&json).map_err(|e| e.context(ErrorKind::StoredTokenCorrupt))
+    }
+
+    /// Writes this token as JSON to a file, e.g. to reuse it on the next start of an app
+    pub fn to_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let json = serde_json::to_string(self).map_err(|e| e.context(ErrorKind::JsonSerializationFailed))?;
+        fs::write(path, json).map_err(|e| e.context(ErrorKind::TokenFileAccessFailed))
+    }
+
+    /// Reads a token from the OS keyring as written by `Token::save_to_keyring`
+    #[cfg(feature = "keyring")]
+    pub fn load_from_keyring(service: &str) -> Result<Token> {
+        let json = keyring_entry(service)?
+            .get_password()
+            .map_err(|e| e.context(ErrorKind::KeyringAccessFailed))?;
+        serde_json::from_str(&json).map_err(|e| e.context(ErrorKind::StoredTokenCorrupt))
+    }
+
+    /// Writes this token as JSON to the OS keyring under `service`, replacing any token stored before
+    #[cfg(feature = "keyring")]
+    pub fn save_to_keyring(&self, service: &str) -> Result<()> {
+        let json = serde_json::to_string(self).map_err(|e| e.context(ErrorKind::JsonSerializationFailed))?;
+        keyring_entry(service)?
+            .set_password(&json)
+            .map_err(|e| e.context(ErrorKind::KeyringAccessFailed))
+    }
+
+    /// The scopes of `expected` this token has not been granted
+    pub fn missing_scopes(&self, expected: &[Scope]) -> Vec<Scope> {
+        expected.iter().filter(|x| !self.scope.contains(x)).cloned().collect()
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_entry(service: &str) -> Result<keyring::Entry> {
+    keyring::Entry::new(service, "token").map_err(|e| e.context(ErrorKind::KeyringAccessFailed))
+}
+
+/// The raw response of the token endpoint, cf. `UnauthenticatedClient::authenticate_with_response`
+///
+/// It is validated into a `Token` so a malformed response cannot yield a client that only fails on its first API
+/// call. Fields this crate doesn't know are kept in `extra`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TokenResponse {
+    pub access_token: Option<String>,
+    pub refresh_token: Option<String>,
+    #[serde(default)]
+    pub scope: Vec<Scope>,
+    pub expires_in: Option<u64>,
+    pub expire_in: Option<u64>,
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl TokenResponse {
+    /// Validates the response and compares the granted scopes to `requested`
+    ///
+    /// Returns the token and the requested scopes Netatmo didn't grant, which are logged as warning, too. Nothing is
+    /// enforced: a token lacking scopes is still valid for the API calls its scopes cover.
+    fn to_token(&self, requested: &[Scope]) -> Result<(Token, Vec<Scope>)> {
+        let access_token = non_empty("access_token", self.access_token.clone())?;
+        let refresh_token = non_empty("refresh_token", self.refresh_token.clone())?;
+        let expires_in = match self.expires_in {
+            Some(expires_in) if expires_in > 0 => expires_in,
+            Some(_) => return Err(invalid_token_response("expires_in is 0")),
+            None => return Err(invalid_token_response("expires_in is missing")),
+        };
+
+        let token = Token {
+            access_token,
+            refresh_token,
+            scope: self.scope.clone(),
+            expires_in,
+            expire_in: self.expire_in.unwrap_or(expires_in),
+        };
+        let missing = log_missing_scopes(&token, requested);
+
+        Ok((token, missing))
+    }
+}
+
+fn log_missing_scopes(token: &Token, expected: &[Scope]) -> Vec<Scope> {
+    let missing = token.missing_scopes(expected);
+    if !missing.is_empty() {
+        let missing_str = missing.iter().map(|x| x.to_scope_str()).collect::<Vec<_>>().join(", ");
+        warn!("Token has not been granted the expected scopes {}", missing_str);
+    }
+
+    missing
+}
+
+fn non_empty(field: &str, value: Option<String>) -> Result<String> {
+    match value {
+        Some(value) if !value.trim().is_empty() => Ok(value),
+        Some(_) => Err(invalid_token_response(&format!("{} is empty", field))),
+        None => Err(invalid_token_response(&format!("{} is missing", field))),
+    }
+}
+
+fn invalid_token_response(detail: &str) -> crate::errors::Error {
+    ErrorKind::InvalidTokenResponse {
+        detail: detail.to_string(),
+    }
+    .into()
+}
+
 #[allow(clippy::implicit_hasher)]
 impl<'a> From<&'a UnauthenticatedClient<'a>> for HashMap<&str, &'a str> {
     fn from(uc: &'a UnauthenticatedClient) -> HashMap<&'static str, &'a str> {
@@ -23,7 +137,7 @@ impl<'a> From<&'a UnauthenticatedClient<'a>> for HashMap<&str, &'a str> {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Scope {
     ReadStation,
@@ -55,7 +169,14 @@ impl fmt::Display for Scope {
 }
 
 impl Scope {
-    fn to_scope_str(&self) -> &'static str {
+    /// The scopes `NetatmoClient::new` asks for unless set by `UnauthenticatedClient::scopes`: reading weather
+    /// stations and thermostats
+    ///
+    /// Scopes to control devices or to access cameras and their pictures must be asked for explicitly, so apps only get
+    /// these privileges on purpose.
+    pub const DEFAULT: [Scope; 2] = [Scope::ReadStation, Scope::ReadThermostat];
+
+    pub(crate) fn to_scope_str(self) -> &'static str {
         match self {
             Scope::ReadStation => "read_station",
             Scope::ReadThermostat => "read_thermostat",
@@ -70,10 +191,339 @@ impl Scope {
     }
 }
 
+/// The API calls of this crate, e.g. to look up the scopes they need, cf. `AuthenticatedClient::scopes_required_for`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Endpoint {
+    GetEvents,
+    GetHomeStatus,
+    GetHomesData,
+    GetStationData,
+    GetHomecoachsData,
+    GetMeasure,
+    SetRoomThermpoint,
+    SetState,
+    SyncHomeSchedule,
+}
+
+impl Endpoint {
+    pub const ALL: [Endpoint; 9] = [
+        Endpoint::GetEvents,
+        Endpoint::GetHomeStatus,
+        Endpoint::GetHomesData,
+        Endpoint::GetStationData,
+        Endpoint::GetHomecoachsData,
+        Endpoint::GetMeasure,
+        Endpoint::SetRoomThermpoint,
+        Endpoint::SetState,
+        Endpoint::SyncHomeSchedule,
+    ];
+
+    /// The scopes granting access to the endpoint
+    ///
+    /// Endpoints serving several kinds of devices list the scope of each kind; any of them is enough to call the
+    /// endpoint, but only returns the data of the respective devices. `SetState` lists none: the scope it needs
+    /// depends on the type of the modules it addresses, e.g. plugs or shutters, which Netatmo does not document.
+    pub fn scopes(self) -> &'static [Scope] {
+        match self {
+            Endpoint::GetEvents => &[Scope::ReadCamera, Scope::ReadPresence],
+            Endpoint::GetHomeStatus | Endpoint::GetHomesData => {
+                &[Scope::ReadThermostat, Scope::ReadCamera, Scope::ReadPresence]
+            }
+            Endpoint::GetStationData => &[Scope::ReadStation],
+            Endpoint::GetHomecoachsData => &[Scope::ReadHomecoach],
+            Endpoint::GetMeasure => &[Scope::ReadStation, Scope::ReadThermostat, Scope::ReadHomecoach],
+            Endpoint::SetRoomThermpoint | Endpoint::SyncHomeSchedule => &[Scope::WriteThermostat],
+            Endpoint::SetState => &[],
+        }
+    }
+}
+
+/// Checks `refresh_token` for obvious mistakes without contacting Netatmo
+///
+/// Tokens copied from shell variables or files often carry trailing newlines or enclosing quotes, which Netatmo only
+/// rejects with an opaque `invalid_grant`.
+pub fn validate_refresh_token(refresh_token: &str) -> Result<()> {
+    let reason = if refresh_token.trim().is_empty() {
+        "it is empty"
+    } else if refresh_token.trim() != refresh_token {
+        "it has leading or trailing whitespace"
+    } else if refresh_token.starts_with(['"', '\'']) || refresh_token.ends_with(['"', '\'']) {
+        "it is enclosed in quotes"
+    } else {
+        return Ok(());
+    };
+
+    Err(ErrorKind::MalformedRefreshToken {
+        reason: reason.to_string(),
+    }
+    .into())
+}
+
 pub(crate) fn refresh_token(unauthenticated_client: &UnauthenticatedClient, refresh_token: &str) -> Result<Token> {
+    refresh_token_with_response(unauthenticated_client, refresh_token).map(|(token, _)| token)
+}
+
+/// Refreshes like `refresh_token`, additionally returning the raw response of the token endpoint
+pub(crate) fn refresh_token_with_response(
+    unauthenticated_client: &UnauthenticatedClient,
+    refresh_token: &str,
+) -> Result<(Token, TokenResponse)> {
     let mut params: HashMap<_, _> = unauthenticated_client.into();
     params.insert("grant_type", "refresh_token");
     params.insert("refresh_token", refresh_token);
 
-    unauthenticated_client.call("oauth2/token", "https://api.netatmo.com/oauth2/token", &params)
+    request_token(unauthenticated_client, &params)
+}
+
+pub(crate) fn authorization_code(
+    unauthenticated_client: &UnauthenticatedClient,
+    code: &str,
+    redirect_uri: &str,
+) -> Result<Token> {
+    let scope = scope_param(&unauthenticated_client.scopes);
+    let mut params: HashMap<_, _> = unauthenticated_client.into();
+    params.insert("grant_type", "authorization_code");
+    params.insert("code", code);
+    params.insert("redirect_uri", redirect_uri);
+    if !scope.is_empty() {
+        params.insert("scope", &scope);
+    }
+
+    request_token(unauthenticated_client, &params).map(|(token, _)| token)
+}
+
+pub(crate) fn authorization_url(
+    unauthenticated_client: &UnauthenticatedClient,
+    redirect_uri: &str,
+    state: &str,
+) -> String {
+    let mut params = vec![
+        (
+            "client_id",
+            unauthenticated_client.client_credentials.client_id.to_string(),
+        ),
+        ("redirect_uri", redirect_uri.to_string()),
+    ];
+    let scope = scope_param(&unauthenticated_client.scopes);
+    if !scope.is_empty() {
+        params.push(("scope", scope));
+    }
+    params.push(("state", state.to_string()));
+
+    let url = unauthenticated_client
+        .transport
+        .base_urls()
+        .oauth_url("/oauth2/authorize");
+    reqwest::Url::parse_with_params(&url, &params)
+        .expect("base URLs are valid")
+        .into()
+}
+
+/// Netatmo expects scopes separated by spaces
+fn scope_param(scopes: &[Scope]) -> String {
+    scopes.iter().map(|x| x.to_scope_str()).collect::<Vec<_>>().join(" ")
+}
+
+fn request_token(
+    unauthenticated_client: &UnauthenticatedClient,
+    params: &HashMap<&str, &str>,
+) -> Result<(Token, TokenResponse)> {
+    let response = unauthenticated_client.call::<TokenResponse>("oauth2/token", "/oauth2/token", params)?;
+    let (token, _) = response.to_token(&unauthenticated_client.scopes)?;
+
+    Ok((token, response))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::client::{
+        transport::{replay_transport, TranscriptEntry},
+        ClientCredentials, NetatmoClient,
+    };
+
+    use spectral::prelude::*;
+
+    fn token(json: &str) -> Result<Token> {
+        serde_json::from_str::<TokenResponse>(json)
+            .unwrap()
+            .to_token(&[Scope::ReadStation])
+            .map(|(token, _)| token)
+    }
+
+    #[test]
+    fn scopes_required_for() {
+        use crate::client::AuthenticatedClient;
+        use std::collections::BTreeSet;
+
+        let enabled = [
+            Endpoint::GetStationData,
+            Endpoint::GetMeasure,
+            Endpoint::SetRoomThermpoint,
+        ];
+        let scopes = enabled
+            .iter()
+            .flat_map(|x| AuthenticatedClient::scopes_required_for(*x))
+            .collect::<BTreeSet<_>>();
+
+        assert_that(&scopes.into_iter().copied().collect::<Vec<_>>()).is_equal_to(vec![
+            Scope::ReadStation,
+            Scope::ReadThermostat,
+            Scope::WriteThermostat,
+            Scope::ReadHomecoach,
+        ]);
+        assert_that(
+            &Endpoint::ALL
+                .iter()
+                .all(|x| *x == Endpoint::SetState || !x.scopes().is_empty()),
+        )
+        .is_true();
+    }
+
+    #[test]
+    fn missing_requested_scopes() {
+        let response = serde_json::from_str::<TokenResponse>(
+            r#"{"access_token": "access|1", "refresh_token": "refresh|1", "scope": ["read_station"], "expires_in": 10800}"#,
+        )
+        .unwrap();
+
+        let missing = response.to_token(&Scope::DEFAULT).map(|(_, missing)| missing);
+
+        assert_that(&missing).is_ok().is_equal_to(vec![Scope::ReadThermostat]);
+    }
+
+    #[test]
+    fn valid_token_response() {
+        let token = token(
+            r#"{"access_token": "access|1", "refresh_token": "refresh|1", "scope": ["read_station"], "expires_in": 10800}"#,
+        );
+
+        assert_that(&token).is_ok();
+        let token = token.unwrap();
+        assert_that(&token.scope).is_equal_to(vec![Scope::ReadStation]);
+        assert_that(&token.expire_in).is_equal_to(10800);
+    }
+
+    #[test]
+    fn token_response_missing_access_token() {
+        let token = token(r#"{"refresh_token": "refresh|1", "scope": [], "expires_in": 10800}"#);
+
+        assert_that(&token.map_err(|e| e.kind().clone())).is_err_containing(ErrorKind::InvalidTokenResponse {
+            detail: "access_token is missing".to_string(),
+        });
+    }
+
+    fn replay_token_endpoint(name: &str, entries: &[(u16, &str)]) -> UnauthenticatedClient<'static> {
+        let entries: Vec<_> = entries
+            .iter()
+            .map(|(status, body)| TranscriptEntry::response("oauth2/token", *status, body))
+            .collect();
+        let transport = replay_transport(name, &entries);
+
+        static CREDENTIALS: ClientCredentials = ClientCredentials {
+            client_id: "a_client",
+            client_secret: "a_secret",
+        };
+        UnauthenticatedClient {
+            client_credentials: &CREDENTIALS,
+            scopes: Vec::new(),
+            transport,
+        }
+    }
+
+    const TOKEN: &str =
+        r#"{"access_token": "access|2", "refresh_token": "refresh|2", "scope": ["read_station"], "expires_in": 10800}"#;
+
+    #[test]
+    fn refresh_retries_server_errors() {
+        let client = replay_token_endpoint("retry-server-error", &[(502, "Bad Gateway"), (200, TOKEN)]);
+
+        let token = refresh_token(&client, "refresh|1");
+
+        assert_that(&token.map(|x| x.access_token)).is_ok_containing("access|2".to_string());
+    }
+
+    #[test]
+    fn authenticate_with_response() {
+        let response = r#"{"access_token": "access|2", "refresh_token": "refresh|2", "scope": ["read_station"],
+            "expires_in": 10800, "expire_in": 10800, "restricted": false}"#;
+        let client = replay_token_endpoint("with-response", &[(200, response)]);
+
+        let (client, response) = client
+            .authenticate_with_response("refresh|1")
+            .expect("failed to authenticate");
+
+        assert_that(&client.token().access_token.as_str()).is_equal_to("access|2");
+        assert_that(&response.expires_in).is_equal_to(Some(10800));
+        assert_that(&response.scope).is_equal_to(vec![Scope::ReadStation]);
+        assert_that(&response.extra.get("restricted")).is_equal_to(Some(&serde_json::json!(false)));
+    }
+
+    #[test]
+    fn refresh_does_not_retry_invalid_grant() {
+        let client = replay_token_endpoint(
+            "retry-invalid-grant",
+            &[(400, r#"{"error": "invalid_grant"}"#), (200, TOKEN)],
+        );
+
+        let token = refresh_token(&client, "refresh|1");
+
+        assert_that(&token).is_err();
+    }
+
+    #[test]
+    fn validate_refresh_token() {
+        let reason = |token| match super::validate_refresh_token(token).map_err(|e| e.kind().clone()) {
+            Err(ErrorKind::MalformedRefreshToken { reason }) => Some(reason),
+            _ => None,
+        };
+
+        assert_that(&reason("5c8a8d2d|b2a8e1f0")).is_none();
+        assert_that(&reason(" \n")).is_equal_to(Some("it is empty".to_string()));
+        assert_that(&reason("5c8a8d2d|b2a8e1f0\n"))
+            .is_equal_to(Some("it has leading or trailing whitespace".to_string()));
+        assert_that(&reason("\"5c8a8d2d|b2a8e1f0\"")).is_equal_to(Some("it is enclosed in quotes".to_string()));
+        assert_that(&reason("5c8a8d2db2a8e1f0")).is_none();
+    }
+
+    #[test]
+    fn authorization_url_contains_scopes() {
+        let credentials = ClientCredentials {
+            client_id: "client",
+            client_secret: "secret",
+        };
+        let client = NetatmoClient::new(&credentials).scopes(&[Scope::ReadStation, Scope::ReadThermostat]);
+
+        let url = client.authorization_url("http://localhost:8080/callback", "xyz");
+
+        assert_that(&url.as_str()).is_equal_to(
+            "https://api.netatmo.com/oauth2/authorize?client_id=client&redirect_uri=http%3A%2F%2Flocalhost%3A8080%2Fcallback\
+             &scope=read_station+read_thermostat&state=xyz",
+        );
+    }
+
+    #[test]
+    fn authorization_url_contains_default_scopes() {
+        let credentials = ClientCredentials {
+            client_id: "client",
+            client_secret: "secret",
+        };
+
+        let url = NetatmoClient::new(&credentials).authorization_url("http://localhost:8080/callback", "xyz");
+        let without_scopes = NetatmoClient::new(&credentials)
+            .scopes(&[])
+            .authorization_url("http://localhost:8080/callback", "xyz");
+
+        assert_that(&url.as_str()).contains("&scope=read_station+read_thermostat&");
+        assert_that(&without_scopes.contains("scope=")).is_false();
+    }
+
+    #[test]
+    fn token_response_empty_refresh_token() {
+        let token = token(r#"{"access_token": "access|1", "refresh_token": "", "expires_in": 10800}"#);
+
+        assert_that(&token.map_err(|e| e.kind().clone())).is_err_containing(ErrorKind::InvalidTokenResponse {
+            detail: "refresh_token is empty".to_string(),
+        });
+    }
 }
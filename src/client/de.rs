@@ -0,0 +1,68 @@
+//! Deserialization helpers for fields Netatmo doesn't type consistently
+
+use serde::{de, Deserialize, Deserializer};
+
+/// A number Netatmo sends either as JSON number or as string, depending on endpoint and version
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StrOrNum {
+    Num(f64),
+    Str(String),
+}
+
+impl StrOrNum {
+    fn into_f64<E: de::Error>(self) -> Result<f64, E> {
+        match self {
+            StrOrNum::Num(x) => Ok(x),
+            StrOrNum::Str(s) => s.trim().parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+/// Deserializes a number sent as JSON number or as string, e.g. `1.5` or `"1.5"`
+pub(crate) fn f64_from_str_or_num<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    StrOrNum::deserialize(deserializer)?.into_f64()
+}
+
+/// Deserializes a list of numbers each sent as JSON number or as string, cf. `f64_from_str_or_num`
+pub(crate) fn vec_f64_from_str_or_num<'de, D>(deserializer: D) -> Result<Vec<f64>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<StrOrNum>::deserialize(deserializer)?
+        .into_iter()
+        .map(StrOrNum::into_f64)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[derive(Debug, Deserialize)]
+    struct Value {
+        #[serde(deserialize_with = "f64_from_str_or_num")]
+        value: f64,
+    }
+
+    #[test]
+    fn str_and_num_parse_identically() {
+        let num: Value = serde_json::from_str(r#"{"value": 1.5}"#).unwrap();
+        let string: Value = serde_json::from_str(r#"{"value": "1.5"}"#).unwrap();
+
+        assert_that(&num.value).is_equal_to(1.5);
+        assert_that(&string.value).is_equal_to(1.5);
+    }
+
+    #[test]
+    fn invalid_string() {
+        let res = serde_json::from_str::<Value>(r#"{"value": "north"}"#);
+
+        assert_that(&res).is_err();
+    }
+}
@@ -0,0 +1,145 @@
+use crate::client::{get_home_status, get_homes_data, get_station_data};
+use crate::ModuleType;
+
+use std::convert::TryFrom;
+
+/// A Netatmo device or module regardless of the API call that returned it
+///
+/// Not every call reports every attribute, e.g. `get_homes_data` doesn't report whether a module is reachable, so those
+/// attributes are optional.
+pub trait Device {
+    fn id(&self) -> &str;
+
+    /// The name given by the user
+    fn name(&self) -> Option<&str>;
+
+    fn module_type(&self) -> ModuleType;
+
+    fn is_reachable(&self) -> Option<bool>;
+
+    /// The firmware revision
+    fn firmware(&self) -> Option<u64>;
+}
+
+impl Device for get_station_data::Device {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.module_name.as_deref().or(Some(&self.station_name))
+    }
+
+    fn module_type(&self) -> ModuleType {
+        ModuleType::from(self.type_info.as_str())
+    }
+
+    fn is_reachable(&self) -> Option<bool> {
+        Some(self.reachable)
+    }
+
+    fn firmware(&self) -> Option<u64> {
+        Some(self.firmware)
+    }
+}
+
+impl Device for get_station_data::Module {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(&self.module_name)
+    }
+
+    fn module_type(&self) -> ModuleType {
+        ModuleType::from(self.type_info.as_str())
+    }
+
+    fn is_reachable(&self) -> Option<bool> {
+        Some(self.reachable)
+    }
+
+    fn firmware(&self) -> Option<u64> {
+        Some(self.firmware)
+    }
+}
+
+impl Device for get_homes_data::Module {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> Option<&str> {
+        Some(&self.name)
+    }
+
+    fn module_type(&self) -> ModuleType {
+        ModuleType::from(self.type_field.as_str())
+    }
+
+    fn is_reachable(&self) -> Option<bool> {
+        None
+    }
+
+    fn firmware(&self) -> Option<u64> {
+        None
+    }
+}
+
+impl Device for get_home_status::Module {
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn name(&self) -> Option<&str> {
+        None
+    }
+
+    fn module_type(&self) -> ModuleType {
+        ModuleType::from(self.type_field.as_str())
+    }
+
+    fn is_reachable(&self) -> Option<bool> {
+        self.reachable
+    }
+
+    fn firmware(&self) -> Option<u64> {
+        u64::try_from(self.firmware_revision).ok()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn heterogeneous_devices() {
+        let configured = get_homes_data::Module {
+            id: "70:ee:50:00:00:01".to_string(),
+            type_field: "NATherm1".to_string(),
+            name: "Living room".to_string(),
+            ..get_homes_data::Module::default()
+        };
+        let status = get_home_status::Module {
+            id: "70:ee:50:00:00:02".to_string(),
+            type_field: "NACamera".to_string(),
+            firmware_revision: 372,
+            reachable: Some(false),
+            ..get_home_status::Module::default()
+        };
+        let devices: Vec<&dyn Device> = vec![&configured, &status];
+
+        let unreachable: Vec<_> = devices
+            .iter()
+            .filter(|x| x.is_reachable() == Some(false))
+            .map(|x| (x.id(), x.module_type(), x.firmware()))
+            .collect();
+
+        assert_that(&devices[0].name()).is_equal_to(Some("Living room"));
+        assert_that(&devices[0].module_type()).is_equal_to(ModuleType::Thermostat);
+        assert_that(&unreachable).is_equal_to(vec![("70:ee:50:00:00:02", ModuleType::IndoorCamera, Some(372))]);
+    }
+}
@@ -0,0 +1,302 @@
+use crate::{
+    client::{module_type::ModuleType, AuthenticatedClient},
+    errors::{ErrorKind, Result},
+};
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Events {
+    pub body: Body,
+    pub status: String,
+    pub time_server: i64,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Body {
+    pub home: Home,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Home {
+    pub id: String,
+    #[serde(default)]
+    pub events: Vec<Event>,
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub type_field: String,
+    pub time: i64,
+    pub module_id: String,
+    pub message: Option<String>,
+    pub person_id: Option<String>,
+    pub video_id: Option<String>,
+    pub video_status: Option<String>,
+    /// The picture taken at the event, cf. `MediaRef::download`
+    pub snapshot: Option<MediaRef>,
+    /// The cropped picture of the detected person, animal, or vehicle, cf. `MediaRef::download`
+    pub vignette: Option<MediaRef>,
+}
+
+impl Event {
+    /// The snapshot and vignette of this event, if any
+    pub fn media(&self) -> impl Iterator<Item = &MediaRef> {
+        self.snapshot.iter().chain(self.vignette.iter())
+    }
+}
+
+/// A reference to a picture of an event which is downloaded on demand
+///
+/// Older cameras reference pictures by `id` and `key`, newer ones by a pre-signed `url` instead.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MediaRef {
+    #[serde(default)]
+    pub id: String,
+    #[serde(default)]
+    pub key: String,
+    pub version: Option<i64>,
+    pub url: Option<String>,
+}
+
+impl MediaRef {
+    /// Downloads the picture, usually a JPEG
+    // cf. https://dev.netatmo.com/apidocumentation/security#getcamerapicture
+    pub fn download(&self, client: &AuthenticatedClient) -> Result<Vec<u8>> {
+        if let Some(ref url) = self.url {
+            return client.fetch_bytes("get_camera_picture", url);
+        }
+        let mut params = HashMap::default();
+        params.insert("image_id", self.id.as_str());
+        params.insert("key", self.key.as_str());
+        client.call_bytes("get_camera_picture", "/api/getcamerapicture", &params)
+    }
+}
+
+pub struct Parameters<'a> {
+    home_id: &'a str,
+    device_types: Option<&'a [ModuleType]>,
+    event_id: Option<&'a str>,
+    size: Option<usize>,
+}
+
+impl<'a> Parameters<'a> {
+    pub fn new(home_id: &'a str) -> Self {
+        Parameters {
+            home_id,
+            device_types: None,
+            event_id: None,
+            size: None,
+        }
+    }
+
+    /// Restricts the events to the given device types which must all be security devices, cf. `ModuleType::is_security`
+    pub fn device_types(self, device_types: &'a [ModuleType]) -> Self {
+        Parameters {
+            device_types: Some(device_types),
+            ..self
+        }
+    }
+
+    /// Anchors the returned events at the event `event_id`; Netatmo returns the events around it, not only the newer
+    /// ones, cf. `AuthenticatedClient::new_events_since`
+    pub fn event_id(self, event_id: &'a str) -> Self {
+        Parameters {
+            event_id: Some(event_id),
+            ..self
+        }
+    }
+
+    pub fn size(self, size: usize) -> Self {
+        Parameters {
+            size: Some(size),
+            ..self
+        }
+    }
+
+    fn validate(&self) -> Result<()> {
+        if let Some(invalid) = self.device_types.and_then(|x| x.iter().find(|t| !t.is_security())) {
+            return Err(ErrorKind::InvalidParameter {
+                name: "device_types",
+                reason: format!("'{}' is not a security device type", invalid),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+#[allow(clippy::implicit_hasher)]
+impl<'a> From<&'a Parameters<'a>> for HashMap<&str, String> {
+    fn from(p: &'a Parameters) -> HashMap<&'static str, String> {
+        let mut map = HashMap::default();
+        map.insert("home_id", p.home_id.to_string());
+        if let Some(device_types) = p.device_types {
+            let device_types = device_types
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .as_slice()
+                .join(",");
+            map.insert("device_types", device_types);
+        }
+        if let Some(event_id) = p.event_id {
+            map.insert("event_id", event_id.to_string());
+        }
+        if let Some(size) = p.size {
+            map.insert("size", size.to_string());
+        }
+
+        map
+    }
+}
+
+// cf. https://dev.netatmo.com/apidocumentation/security#getevents
+pub(crate) fn get_events(client: &AuthenticatedClient, parameters: &Parameters) -> Result<Events> {
+    parameters.validate()?;
+    let params: HashMap<&str, String> = parameters.into();
+    let params = params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+    client.call("get_events", "/api/getevents", &params)
+}
+
+pub(crate) fn new_events_since(client: &AuthenticatedClient, home_id: &str, last_event_id: &str) -> Result<Vec<Event>> {
+    let latest = get_events(client, &Parameters::new(home_id))?.body.home.events;
+    let events = if latest.iter().any(|x| x.id == last_event_id) {
+        latest
+    } else {
+        // More new events than fit into one response, or the anchor is that old that it has dropped out; the
+        // response anchored at the last event at least contains the events right after it
+        let mut events = get_events(client, &Parameters::new(home_id).event_id(last_event_id))?
+            .body
+            .home
+            .events;
+        events.extend(latest);
+        events
+    };
+    events_after(last_event_id, events).ok_or_else(|| {
+        ErrorKind::EventNotFound {
+            event_id: last_event_id.to_string(),
+        }
+        .into()
+    })
+}
+
+/// The events newer than the event `anchor`, oldest first and without duplicates
+///
+/// Returns `None` if `anchor` isn't contained in `events`, as there is no reference point to tell newer events from
+/// older ones.
+fn events_after(anchor: &str, mut events: Vec<Event>) -> Option<Vec<Event>> {
+    let anchor_time = events.iter().find(|x| x.id == anchor)?.time;
+    events.retain(|x| x.id != anchor && x.time >= anchor_time);
+    events.sort_by(|a, b| a.time.cmp(&b.time).then_with(|| a.id.cmp(&b.id)));
+    events.dedup_by(|a, b| a.id == b.id);
+
+    Some(events)
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+    use crate::client::transport::{replay_transcript, TranscriptEntry};
+
+    #[test]
+    fn device_types_are_serialized() {
+        let device_types = [ModuleType::Doorbell, ModuleType::OutdoorCamera];
+        let parameters = Parameters::new("a_home").device_types(&device_types);
+
+        let params: HashMap<&str, String> = (&parameters).into();
+
+        assert_that(&params.get("device_types")).is_equal_to(Some(&"NDB,NOC".to_string()));
+    }
+
+    fn event(id: &str, time: i64) -> Event {
+        Event {
+            id: id.to_string(),
+            time,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn events_after_anchor() {
+        let events = vec![
+            event("e4", 400),
+            event("e3", 300),
+            event("e2", 200),
+            event("e1", 100),
+            event("e3", 300),
+        ];
+
+        let events = events_after("e2", events);
+
+        assert_that(&events).is_equal_to(Some(vec![event("e3", 300), event("e4", 400)]));
+    }
+
+    #[test]
+    fn events_after_unknown_anchor() {
+        let events = events_after("e0", vec![event("e2", 200), event("e1", 100)]);
+
+        assert_that(&events).is_none();
+    }
+
+    #[test]
+    fn parse_media() {
+        let json = r#"{
+  "id": "5c8a8d2d",
+  "type": "person",
+  "time": 1556451224,
+  "module_id": "70:ee:50:00:00:01",
+  "snapshot": {"id": "5c8a8d2e", "version": 1, "key": "a1b2c3"},
+  "vignette": {"url": "https://netatmocameraimage.blob.core.windows.net/production/5c8a8d2f"}
+}"#;
+
+        let event: Event = serde_json::from_str(json).expect("failed to parse event");
+
+        let media: Vec<_> = event.media().cloned().collect();
+        assert_that(&media).is_equal_to(vec![
+            MediaRef {
+                id: "5c8a8d2e".to_string(),
+                key: "a1b2c3".to_string(),
+                version: Some(1),
+                url: None,
+            },
+            MediaRef {
+                url: Some("https://netatmocameraimage.blob.core.windows.net/production/5c8a8d2f".to_string()),
+                ..MediaRef::default()
+            },
+        ]);
+    }
+
+    #[test]
+    fn download_media() {
+        let client = replay_transcript("media", &[TranscriptEntry::response("get_camera_picture", 200, "JFIF")]);
+        let snapshot = MediaRef {
+            id: "5c8a8d2e".to_string(),
+            key: "a1b2c3".to_string(),
+            ..MediaRef::default()
+        };
+
+        let picture = snapshot.download(&client);
+
+        assert_that(&picture).is_ok_containing(b"JFIF".to_vec());
+    }
+
+    #[test]
+    fn device_types_must_be_security_devices() {
+        let device_types = [ModuleType::Doorbell, ModuleType::Thermostat];
+        let parameters = Parameters::new("a_home").device_types(&device_types);
+
+        let res = parameters.validate().map_err(|e| e.kind().clone());
+
+        assert_that(&res).is_err_containing(ErrorKind::InvalidParameter {
+            name: "device_types",
+            reason: "'NATherm1' is not a security device type".to_string(),
+        });
+    }
+}
@@ -1,7 +1,14 @@
-use crate::{client::AuthenticatedClient, errors::Result};
+use crate::{
+    client::{
+        de::f64_from_str_or_num,
+        module_type::{Battery, BatteryLevel, ModuleType},
+        AuthenticatedClient,
+    },
+    errors::Result,
+};
 
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, convert::TryFrom};
 
 pub use crate::get_homes_data::GatewayType;
 
@@ -22,6 +29,18 @@ pub struct Home {
     pub id: String,
     pub modules: Vec<Module>,
     pub rooms: Vec<Room>,
+    /// Whether a home with a reversible heat pump currently heats or cools; absent for heating-only homes
+    pub temperature_control_mode: Option<HvacMode>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HvacMode {
+    Heating,
+    Cooling,
+    /// Any mode unknown to this crate
+    #[serde(other)]
+    Unknown,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -52,20 +71,293 @@ pub struct Module {
     pub last_smoke_detected_end_time: Option<i64>,
     pub last_seen: Option<i64>,
     pub last_wifi_connection: Option<i64>,
+    pub on: Option<bool>,
+    pub brightness: Option<i64>,
+    pub monitoring: Option<String>,
+    pub sd_status: Option<SdStatus>,
+    pub alim_status: Option<AlimStatus>,
+    /// The position of a shutter or window from 0 (closed) to 100 (open)
+    pub current_position: Option<i64>,
+    /// The position a shutter or window is moving to from 0 (closed) to 100 (open)
+    pub target_position: Option<i64>,
+}
+
+/// The state of the SD card of a camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "i64", into = "i64")]
+pub enum SdStatus {
+    Missing,
+    Inserted,
+    Formatted,
+    Ready,
+    Defective,
+    Incompatible,
+    TooSmall,
+    /// Any status unknown to this crate
+    Unknown(i64),
+}
+
+impl SdStatus {
+    /// Whether the camera can record to the SD card
+    pub fn is_ok(self) -> bool {
+        matches!(self, SdStatus::Inserted | SdStatus::Formatted | SdStatus::Ready)
+    }
+}
+
+impl From<i64> for SdStatus {
+    fn from(status: i64) -> Self {
+        match status {
+            1 => SdStatus::Missing,
+            2 => SdStatus::Inserted,
+            3 => SdStatus::Formatted,
+            4 => SdStatus::Ready,
+            5 => SdStatus::Defective,
+            6 => SdStatus::Incompatible,
+            7 => SdStatus::TooSmall,
+            _ => SdStatus::Unknown(status),
+        }
+    }
+}
+
+impl From<SdStatus> for i64 {
+    fn from(status: SdStatus) -> Self {
+        match status {
+            SdStatus::Missing => 1,
+            SdStatus::Inserted => 2,
+            SdStatus::Formatted => 3,
+            SdStatus::Ready => 4,
+            SdStatus::Defective => 5,
+            SdStatus::Incompatible => 6,
+            SdStatus::TooSmall => 7,
+            SdStatus::Unknown(status) => status,
+        }
+    }
+}
+
+/// The state of the power supply of a camera
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "i64", into = "i64")]
+pub enum AlimStatus {
+    /// The power adapter doesn't supply enough power
+    WrongAdapter,
+    Ok,
+    /// Any status unknown to this crate
+    Unknown(i64),
+}
+
+impl From<i64> for AlimStatus {
+    fn from(status: i64) -> Self {
+        match status {
+            1 => AlimStatus::WrongAdapter,
+            2 => AlimStatus::Ok,
+            _ => AlimStatus::Unknown(status),
+        }
+    }
+}
+
+impl From<AlimStatus> for i64 {
+    fn from(status: AlimStatus) -> Self {
+        match status {
+            AlimStatus::WrongAdapter => 1,
+            AlimStatus::Ok => 2,
+            AlimStatus::Unknown(status) => status,
+        }
+    }
+}
+
+/// The status of a module typed by the kind of module
+#[derive(Debug, Clone, PartialEq)]
+pub enum ModuleStatus {
+    /// A Legrand or Velux gateway bridging the modules of its brand
+    Gateway {
+        reachable: Option<bool>,
+        wifi_strength: Option<i64>,
+    },
+    /// A Legrand power plug
+    Plug { reachable: Option<bool>, on: Option<bool> },
+    /// A Legrand dimmer; the brightness ranges from 0 to 100
+    Dimmer {
+        reachable: Option<bool>,
+        on: Option<bool>,
+        brightness: Option<i64>,
+    },
+    /// A thermostat switching a boiler
+    Thermostat(ThermostatStatus),
+    /// An indoor or outdoor camera; `monitoring` is "on" while the camera watches
+    Camera {
+        reachable: Option<bool>,
+        monitoring: Option<String>,
+        sd_status: Option<SdStatus>,
+        alim_status: Option<AlimStatus>,
+    },
+    /// A shutter or window; positions range from 0 (closed) to 100 (open)
+    Shutter {
+        reachable: Option<bool>,
+        current_position: Option<i64>,
+        target_position: Option<i64>,
+    },
+    /// Any other module; its status is only available from the fields of `Module`
+    Other,
+}
+
+/// The status of a thermostat, cf. `ModuleStatus::Thermostat`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThermostatStatus {
+    pub reachable: Option<bool>,
+    pub battery_level: Option<i64>,
+    pub boiler_status: Option<bool>,
+    pub boiler_valve_comfort_boost: Option<bool>,
+    pub anticipating: Option<bool>,
+}
+
+/// Whether a thermostat currently heats, cf. `ThermostatStatus::heating_state`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HeatingState {
+    Idle,
+    Heating,
+    /// Heating with the valves boosted to reach the comfort temperature faster
+    Boosting,
+}
+
+impl ThermostatStatus {
+    /// The heating state combined from `boiler_status` and `boiler_valve_comfort_boost`
+    ///
+    /// | `boiler_status` | `boiler_valve_comfort_boost` | state      |
+    /// |-----------------|------------------------------|------------|
+    /// | `true`          | `true`                       | `Boosting` |
+    /// | `true`          | `false` or absent            | `Heating`  |
+    /// | `false`         | any                          | `Idle`     |
+    /// | absent          | any                          | `None`     |
+    pub fn heating_state(&self) -> Option<HeatingState> {
+        match (self.boiler_status?, self.boiler_valve_comfort_boost) {
+            (true, Some(true)) => Some(HeatingState::Boosting),
+            (true, _) => Some(HeatingState::Heating),
+            (false, _) => Some(HeatingState::Idle),
+        }
+    }
+}
+
+impl Module {
+    pub fn module_type(&self) -> ModuleType {
+        ModuleType::from(self.type_field.as_str())
+    }
+
+    /// The battery as reported by `battery_state`, or else computed from the voltage in `battery_level` by the
+    /// thresholds of the module's type
+    pub fn battery(&self) -> Option<Battery> {
+        let level = match self.battery_state.as_deref().and_then(BatteryLevel::from_state) {
+            Some(level) => level,
+            None => {
+                let voltage = u64::try_from(self.battery_level?).ok()?;
+                self.module_type().battery_level(voltage)?
+            }
+        };
+        Some(Battery {
+            percent: self.battery_percent.and_then(|x| u64::try_from(x).ok()),
+            level,
+        })
+    }
+
+    pub fn status(&self) -> ModuleStatus {
+        match self.module_type() {
+            ModuleType::LegrandGateway | ModuleType::VeluxGateway => ModuleStatus::Gateway {
+                reachable: self.reachable,
+                wifi_strength: self.wifi_strength,
+            },
+            ModuleType::LegrandPlug => ModuleStatus::Plug {
+                reachable: self.reachable,
+                on: self.on,
+            },
+            ModuleType::LegrandDimmer => ModuleStatus::Dimmer {
+                reachable: self.reachable,
+                on: self.on,
+                brightness: self.brightness,
+            },
+            ModuleType::Thermostat => ModuleStatus::Thermostat(ThermostatStatus {
+                reachable: self.reachable,
+                battery_level: self.battery_level,
+                boiler_status: self.boiler_status,
+                boiler_valve_comfort_boost: self.boiler_valve_comfort_boost,
+                anticipating: self.anticipating,
+            }),
+            ModuleType::IndoorCamera | ModuleType::OutdoorCamera => ModuleStatus::Camera {
+                reachable: self.reachable,
+                monitoring: self.monitoring.clone(),
+                sd_status: self.sd_status,
+                alim_status: self.alim_status,
+            },
+            ModuleType::VeluxOpening | ModuleType::RollerShutter => ModuleStatus::Shutter {
+                reachable: self.reachable,
+                current_position: self.current_position,
+                target_position: self.target_position,
+            },
+            _ => ModuleStatus::Other,
+        }
+    }
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Room {
     pub id: String,
     pub reachable: bool,
+    #[serde(deserialize_with = "f64_from_str_or_num")]
     pub therm_measured_temperature: f64,
     pub heating_power_request: i64,
+    #[serde(deserialize_with = "f64_from_str_or_num")]
     pub therm_setpoint_temperature: f64,
     pub therm_setpoint_mode: String,
     pub therm_setpoint_start_time: i64,
     pub therm_setpoint_end_time: i64,
     pub anticipating: bool,
     pub open_window: bool,
+    pub therm_setpoint_fp: Option<String>,
+    pub cooling_setpoint_temperature: Option<f64>,
+    pub cooling_setpoint_mode: Option<String>,
+    pub cooling_setpoint_start_time: Option<i64>,
+    pub cooling_setpoint_end_time: Option<i64>,
+}
+
+impl Room {
+    /// Whether the setpoints, their modes, or the measured temperature differ from `previous`
+    fn differs_from(&self, previous: &Room) -> bool {
+        self.therm_measured_temperature != previous.therm_measured_temperature
+            || self.therm_setpoint_temperature != previous.therm_setpoint_temperature
+            || self.therm_setpoint_mode != previous.therm_setpoint_mode
+            || self.cooling_setpoint_temperature != previous.cooling_setpoint_temperature
+            || self.cooling_setpoint_mode != previous.cooling_setpoint_mode
+    }
+}
+
+/// A room whose status changed, cf. `Home::room_changes`
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoomChange {
+    pub room_id: String,
+    /// The previous status; `None` if the room is new
+    pub previous: Option<Room>,
+    pub current: Room,
+}
+
+impl Home {
+    /// The rooms whose setpoints, setpoint modes, or measured temperature changed compared to `previous`, e.g. to
+    /// only react to changes when polling
+    ///
+    /// Rooms missing from `previous` count as changed; rooms missing from this home are left out.
+    pub fn room_changes(&self, previous: &Home) -> Vec<RoomChange> {
+        self.rooms
+            .iter()
+            .filter_map(|room| {
+                let previous = previous.rooms.iter().find(|x| x.id == room.id);
+                match previous {
+                    Some(previous) if !room.differs_from(previous) => None,
+                    _ => Some(RoomChange {
+                        room_id: room.id.clone(),
+                        previous: previous.cloned(),
+                        current: room.clone(),
+                    }),
+                }
+            })
+            .collect()
+    }
 }
 
 #[derive(Default)]
@@ -117,6 +409,351 @@ impl<'a> From<&'a Parameters<'a>> for HashMap<&str, String> {
 
 pub(crate) fn get_home_status(client: &AuthenticatedClient, parameters: &Parameters) -> Result<HomeStatus> {
     let params: HashMap<&str, String> = parameters.into();
-    let mut params = params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
-    client.call("get_home_status", "https://api.netatmo.com/api/homestatus", &mut params)
+    let params = params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+    client.call("get_home_status", "/api/homestatus", &params)
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+    use crate::client::transport::{replay_transcript, TranscriptEntry};
+
+    fn room(id: &str, measured: f64, setpoint: f64, mode: &str) -> Room {
+        Room {
+            id: id.to_string(),
+            therm_measured_temperature: measured,
+            therm_setpoint_temperature: setpoint,
+            therm_setpoint_mode: mode.to_string(),
+            ..Room::default()
+        }
+    }
+
+    fn home(rooms: Vec<Room>) -> Home {
+        Home {
+            id: "a_home".to_string(),
+            rooms,
+            ..Home::default()
+        }
+    }
+
+    #[test]
+    fn room_changes() {
+        let previous = home(vec![
+            room("kitchen", 20.5, 21.0, "schedule"),
+            room("bedroom", 18.0, 17.0, "schedule"),
+            room("bathroom", 22.0, 22.0, "schedule"),
+        ]);
+        let mut bathroom = room("bathroom", 22.0, 22.0, "schedule");
+        bathroom.heating_power_request = 100;
+        let current = home(vec![
+            room("kitchen", 20.5, 23.0, "manual"),
+            room("bedroom", 18.5, 17.0, "schedule"),
+            bathroom,
+            room("office", 19.0, 19.0, "schedule"),
+        ]);
+
+        let changes = current.room_changes(&previous);
+
+        let ids: Vec<_> = changes.iter().map(|x| x.room_id.as_str()).collect();
+        assert_that(&ids).is_equal_to(vec!["kitchen", "bedroom", "office"]);
+        assert_that(&changes[0].previous.as_ref().map(|x| x.therm_setpoint_temperature)).is_equal_to(Some(21.0));
+        assert_that(&changes[2].previous).is_none();
+        assert_that(&previous.room_changes(&previous)).is_empty();
+    }
+
+    #[test]
+    fn poll_home_status_changes() {
+        let status = |rooms| {
+            serde_json::to_string(&HomeStatus {
+                status: "ok".to_string(),
+                time_server: 1_556_451_492,
+                body: Body { home: home(rooms) },
+            })
+            .unwrap()
+        };
+        let client = replay_transcript(
+            "poll-home-status",
+            &[
+                TranscriptEntry::response(
+                    "get_home_status",
+                    200,
+                    &status(vec![
+                        room("kitchen", 20.5, 21.0, "schedule"),
+                        room("bedroom", 18.0, 17.0, "schedule"),
+                    ]),
+                ),
+                TranscriptEntry::response(
+                    "get_home_status",
+                    200,
+                    &status(vec![
+                        room("kitchen", 20.5, 21.0, "schedule"),
+                        room("bedroom", 18.0, 19.0, "manual"),
+                    ]),
+                ),
+            ],
+        );
+
+        let first = client.poll_home_status_changes("a_home").expect("failed to poll");
+        let second = client.poll_home_status_changes("a_home").expect("failed to poll");
+
+        assert_that(&first.len()).is_equal_to(2);
+        assert_that(&second.iter().map(|x| x.room_id.as_str()).collect::<Vec<_>>()).is_equal_to(vec!["bedroom"]);
+    }
+
+    #[test]
+    fn parse_unknown_temperature_control_mode() {
+        let json = r#"{"id": "a_home", "modules": [], "rooms": [], "temperature_control_mode": "fan"}"#;
+
+        let home: Home = serde_json::from_str(json).expect("failed to parse home");
+
+        assert_that(&home.temperature_control_mode).is_equal_to(Some(HvacMode::Unknown));
+    }
+
+    #[test]
+    fn parse_legrand_modules() {
+        let json = r#"{
+  "status": "ok",
+  "time_server": 1556451492,
+  "body": {
+    "home": {
+      "id": "12345678901234567890abcd",
+      "modules": [
+        {
+          "id": "12:34:56:78:90:AB",
+          "type": "NLG",
+          "firmware_revision": 160,
+          "wifi_strength": 52,
+          "reachable": true
+        },
+        {
+          "id": "12:34:56:78:90:CD",
+          "type": "NLP",
+          "firmware_revision": 62,
+          "bridge": "12:34:56:78:90:AB",
+          "reachable": true,
+          "on": true
+        },
+        {
+          "id": "12:34:56:78:90:EF",
+          "type": "NLF",
+          "firmware_revision": 62,
+          "bridge": "12:34:56:78:90:AB",
+          "reachable": false,
+          "on": false,
+          "brightness": 40
+        }
+      ],
+      "rooms": []
+    }
+  }
+}"#;
+
+        let home_status: HomeStatus = serde_json::from_str(json).expect("failed to parse home status");
+
+        let statuses: Vec<_> = home_status.body.home.modules.iter().map(Module::status).collect();
+        assert_that(&statuses).is_equal_to(vec![
+            ModuleStatus::Gateway {
+                reachable: Some(true),
+                wifi_strength: Some(52),
+            },
+            ModuleStatus::Plug {
+                reachable: Some(true),
+                on: Some(true),
+            },
+            ModuleStatus::Dimmer {
+                reachable: Some(false),
+                on: Some(false),
+                brightness: Some(40),
+            },
+        ]);
+    }
+
+    #[test]
+    fn parse_cameras() {
+        let json = r#"{
+  "status": "ok",
+  "time_server": 1556451492,
+  "body": {
+    "home": {
+      "id": "12345678901234567890abcd",
+      "modules": [
+        {
+          "id": "70:ee:50:00:00:01",
+          "type": "NACamera",
+          "firmware_revision": 372,
+          "monitoring": "on",
+          "sd_status": 4,
+          "alim_status": 2
+        },
+        {
+          "id": "70:ee:50:00:00:02",
+          "type": "NOC",
+          "firmware_revision": 372,
+          "monitoring": "off",
+          "sd_status": 5,
+          "alim_status": 9
+        }
+      ],
+      "rooms": []
+    }
+  }
+}"#;
+
+        let home_status: HomeStatus = serde_json::from_str(json).expect("failed to parse home status");
+
+        let statuses: Vec<_> = home_status.body.home.modules.iter().map(Module::status).collect();
+        assert_that(&statuses).is_equal_to(vec![
+            ModuleStatus::Camera {
+                reachable: None,
+                monitoring: Some("on".to_string()),
+                sd_status: Some(SdStatus::Ready),
+                alim_status: Some(AlimStatus::Ok),
+            },
+            ModuleStatus::Camera {
+                reachable: None,
+                monitoring: Some("off".to_string()),
+                sd_status: Some(SdStatus::Defective),
+                alim_status: Some(AlimStatus::Unknown(9)),
+            },
+        ]);
+        assert_that(&SdStatus::Defective.is_ok()).is_false();
+    }
+
+    #[test]
+    fn battery() {
+        let module = |type_field: &str, battery_state: Option<&str>, battery_level, battery_percent| Module {
+            type_field: type_field.to_string(),
+            battery_state: battery_state.map(str::to_string),
+            battery_level,
+            battery_percent,
+            ..Module::default()
+        };
+
+        let batteries: Vec<_> = [
+            module("NRV", Some("low"), Some(3150), None),
+            module("NATherm1", None, Some(3400), Some(55)),
+            module("NRV", None, Some(2100), None),
+            module("NACamera", None, Some(3400), None),
+        ]
+        .iter()
+        .map(Module::battery)
+        .collect();
+
+        assert_that(&batteries).is_equal_to(vec![
+            Some(Battery {
+                percent: None,
+                level: BatteryLevel::Low,
+            }),
+            Some(Battery {
+                percent: Some(55),
+                level: BatteryLevel::Medium,
+            }),
+            Some(Battery {
+                percent: None,
+                level: BatteryLevel::VeryLow,
+            }),
+            None,
+        ]);
+    }
+
+    #[test]
+    fn parse_shutters() {
+        let json = r#"{
+  "status": "ok",
+  "time_server": 1556451492,
+  "body": {
+    "home": {
+      "id": "12345678901234567890abcd",
+      "modules": [
+        {
+          "id": "00:00:00:00:00:01",
+          "type": "NXG",
+          "firmware_revision": 52,
+          "reachable": true,
+          "wifi_strength": 60
+        },
+        {
+          "id": "00:00:00:00:00:02",
+          "type": "NXO",
+          "firmware_revision": 1,
+          "reachable": true,
+          "current_position": 100,
+          "target_position": 0,
+          "bridge": "00:00:00:00:00:01"
+        },
+        {
+          "id": "00:00:00:00:00:03",
+          "type": "NBR",
+          "firmware_revision": 14,
+          "reachable": false
+        }
+      ],
+      "rooms": []
+    }
+  }
+}"#;
+
+        let home_status: HomeStatus = serde_json::from_str(json).expect("failed to parse home status");
+
+        let statuses: Vec<_> = home_status.body.home.modules.iter().map(Module::status).collect();
+        assert_that(&statuses).is_equal_to(vec![
+            ModuleStatus::Gateway {
+                reachable: Some(true),
+                wifi_strength: Some(60),
+            },
+            ModuleStatus::Shutter {
+                reachable: Some(true),
+                current_position: Some(100),
+                target_position: Some(0),
+            },
+            ModuleStatus::Shutter {
+                reachable: Some(false),
+                current_position: None,
+                target_position: None,
+            },
+        ]);
+    }
+
+    #[test]
+    fn heating_state() {
+        let status = |boiler_status, boiler_valve_comfort_boost| ThermostatStatus {
+            reachable: Some(true),
+            battery_level: None,
+            boiler_status,
+            boiler_valve_comfort_boost,
+            anticipating: None,
+        };
+
+        assert_that(&status(Some(true), Some(true)).heating_state()).is_equal_to(Some(HeatingState::Boosting));
+        assert_that(&status(Some(true), None).heating_state()).is_equal_to(Some(HeatingState::Heating));
+        assert_that(&status(Some(false), Some(true)).heating_state()).is_equal_to(Some(HeatingState::Idle));
+        assert_that(&status(None, Some(true)).heating_state()).is_none();
+    }
+
+    #[test]
+    fn parse_thermostat() {
+        let json = r#"{
+  "id": "12:34:56:78:90:AB",
+  "type": "NATherm1",
+  "firmware_revision": 65,
+  "rf_strength": 58,
+  "battery_level": 3793,
+  "boiler_valve_comfort_boost": false,
+  "boiler_status": true,
+  "anticipating": false,
+  "bridge": "70:ee:50:00:00:00",
+  "battery_state": "high"
+}"#;
+
+        let module: Module = serde_json::from_str(json).expect("failed to parse module");
+
+        match module.status() {
+            ModuleStatus::Thermostat(status) => {
+                assert_that(&status.heating_state()).is_equal_to(Some(HeatingState::Heating))
+            }
+            status => panic!("unexpected status {:?}", status),
+        }
+    }
 }
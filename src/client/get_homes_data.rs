@@ -1,7 +1,12 @@
-use crate::{client::AuthenticatedClient, errors::Result};
+use crate::{
+    client::{get_home_status, get_station_data::unix_now, module_type::ModuleType, AuthenticatedClient},
+    errors::{ErrorKind, Result},
+};
 
-use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, fmt};
+use log::warn;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::{collections::HashMap, fmt, time::Duration};
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct HomesData {
@@ -9,14 +14,50 @@ pub struct HomesData {
     pub status: String,
     pub time_exec: f64,
     pub time_server: i64,
+    /// The homes or modules Netatmo failed to load; the other homes are still returned
+    #[serde(default)]
+    pub errors: Vec<HomeError>,
+}
+
+/// A home or module that couldn't be loaded, cf. `HomesData::errors`
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HomeError {
+    /// Netatmo's error code
+    pub code: i64,
+    /// The id of the home or module
+    pub id: String,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Body {
+    /// The homes that could be parsed; the others are skipped with a warning
+    #[serde(deserialize_with = "de_homes")]
     pub homes: Vec<Home>,
     pub user: User,
 }
 
+/// Parses each home on its own, so an incompletely loaded home doesn't fail the whole response
+fn de_homes<'de, D>(deserializer: D) -> ::std::result::Result<Vec<Home>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let homes = Vec::<Value>::deserialize(deserializer)?
+        .into_iter()
+        .filter_map(|value| {
+            let id = value.get("id").and_then(Value::as_str).unwrap_or_default().to_string();
+            match Home::deserialize(value) {
+                Ok(home) => Some(home),
+                Err(e) => {
+                    warn!("Skipping home '{}' which failed to parse: {}", id, e);
+                    None
+                }
+            }
+        })
+        .collect();
+
+    Ok(homes)
+}
+
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Home {
     pub id: String,
@@ -25,9 +66,14 @@ pub struct Home {
     pub rooms: Vec<Room>,
     pub modules: Vec<Module>,
     pub therm_schedules: Vec<ThermSchedule>,
+    /// Default duration of manual setpoints in minutes, cf. `Home::setpoint_default_duration`
     pub therm_setpoint_default_duration: i64,
     pub therm_mode: String,
     pub schedules: Vec<Schedule>,
+    /// Whether users are notified when everybody left home
+    pub therm_absence_notification: Option<bool>,
+    /// Whether the home switches to away mode automatically when everybody left home
+    pub therm_absence_autoaway: Option<bool>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -45,6 +91,8 @@ pub struct Module {
     #[serde(rename = "type")]
     pub type_field: String,
     pub name: String,
+    /// When the module was installed in seconds since the epoch; 0 if Netatmo doesn't report it, cf. `Module::age`
+    #[serde(default)]
     pub setup_date: i64,
     pub modules_bridged: Option<Vec<String>>,
     pub room_id: Option<String>,
@@ -62,7 +110,7 @@ pub struct ThermSchedule {
     pub id: String,
     pub selected: bool,
     #[serde(rename = "type")]
-    pub type_field: String,
+    pub type_field: ScheduleType,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -76,15 +124,78 @@ pub struct Zone {
     pub name: String,
     pub id: i64,
     #[serde(rename = "type")]
-    pub type_field: i64,
-    pub rooms_temp: Vec<RoomsTemp>,
-    pub rooms: Option<Vec<RoomTemp>>,
+    pub type_field: ZoneType,
+    /// The setpoints of the rooms as returned by older payload versions, cf. `Zone::room_setpoints`
+    #[serde(default)]
+    pub rooms_temp: Vec<RoomSetpoint>,
+    /// The setpoints of the rooms as returned by newer payload versions, cf. `Zone::room_setpoints`
+    pub rooms: Option<Vec<RoomSetpoint>>,
+}
+
+impl Zone {
+    /// The setpoints of the rooms regardless of the payload version, preferring `rooms` over `rooms_temp`
+    pub fn room_setpoints(&self) -> &[RoomSetpoint] {
+        match self.rooms {
+            Some(ref rooms) if !rooms.is_empty() => rooms,
+            _ => &self.rooms_temp,
+        }
+    }
+}
+
+/// The kind of a zone of a schedule
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "i64", into = "i64")]
+pub enum ZoneType {
+    #[default]
+    Comfort,
+    Night,
+    Away,
+    FrostGuard,
+    Custom,
+    Eco,
+    /// Any type unknown to this crate
+    Other(i64),
+}
+
+impl From<i64> for ZoneType {
+    fn from(zone_type: i64) -> Self {
+        match zone_type {
+            0 => ZoneType::Comfort,
+            1 => ZoneType::Night,
+            2 => ZoneType::Away,
+            3 => ZoneType::FrostGuard,
+            4 => ZoneType::Custom,
+            5 => ZoneType::Eco,
+            _ => ZoneType::Other(zone_type),
+        }
+    }
+}
+
+impl From<ZoneType> for i64 {
+    fn from(zone_type: ZoneType) -> Self {
+        match zone_type {
+            ZoneType::Comfort => 0,
+            ZoneType::Night => 1,
+            ZoneType::Away => 2,
+            ZoneType::FrostGuard => 3,
+            ZoneType::Custom => 4,
+            ZoneType::Eco => 5,
+            ZoneType::Other(zone_type) => zone_type,
+        }
+    }
 }
 
+/// The setpoint of a room in a zone
+///
+/// Depending on the payload version, Netatmo names the fields `room_id` and `temp` or `id` and
+/// `therm_setpoint_temperature`; both are accepted, and the latter are written. Zones without thermal meaning have no
+/// setpoint.
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct RoomsTemp {
-    pub room_id: String,
-    pub temp: f64,
+pub struct RoomSetpoint {
+    #[serde(alias = "room_id")]
+    pub id: String,
+    #[serde(rename = "therm_setpoint_temperature", alias = "temp", default)]
+    pub setpoint: Option<f64>,
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -98,13 +209,247 @@ pub struct Schedule {
     pub id: String,
     pub selected: bool,
     #[serde(rename = "type")]
-    pub type_field: String,
+    pub type_field: ScheduleType,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
-pub struct RoomTemp {
+/// The kind of a schedule; homes with heat pumps have cooling schedules besides the heating ones
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum ScheduleType {
+    /// Heating
+    #[default]
+    Therm,
+    Cooling,
+    Electricity,
+    Event,
+    /// Any type unknown to this crate
+    Other(String),
+}
+
+impl fmt::Display for ScheduleType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            ScheduleType::Therm => "therm",
+            ScheduleType::Cooling => "cooling",
+            ScheduleType::Electricity => "electricity",
+            ScheduleType::Event => "event",
+            ScheduleType::Other(s) => s,
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl From<String> for ScheduleType {
+    fn from(s: String) -> Self {
+        match s.as_str() {
+            "therm" => ScheduleType::Therm,
+            "cooling" => ScheduleType::Cooling,
+            "electricity" => ScheduleType::Electricity,
+            "event" => ScheduleType::Event,
+            _ => ScheduleType::Other(s),
+        }
+    }
+}
+
+impl Module {
+    /// The time since this module was installed compared to the local clock; `None` if the setup date is unknown
+    pub fn age(&self) -> Option<Duration> {
+        self.age_at(unix_now())
+    }
+
+    /// The time since this module was installed compared to `now` in seconds since the epoch, e.g.
+    /// `HomesData::time_server`
+    pub fn age_at(&self, now: u64) -> Option<Duration> {
+        match self.setup_date {
+            x if x > 0 => Some(Duration::from_secs(now.saturating_sub(x as u64))),
+            _ => None,
+        }
+    }
+}
+
+impl From<ScheduleType> for String {
+    fn from(schedule_type: ScheduleType) -> Self {
+        schedule_type.to_string()
+    }
+}
+
+impl Home {
+    /// The default duration of manual setpoints
+    pub fn setpoint_default_duration(&self) -> Duration {
+        Duration::from_secs(self.therm_setpoint_default_duration.max(0) as u64 * 60)
+    }
+
+    /// The health of each gateway of this home, i.e. each module bridging other modules like a relay, combined from
+    /// the topology of this home and its `status`
+    ///
+    /// A gateway losing connectivity takes all modules it bridges offline, so this is the first thing to check when the
+    /// modules of a home become unreachable. Values missing from `status` are `None`.
+    pub fn gateway_health(&self, status: &get_home_status::Home) -> Vec<GatewayHealth> {
+        self.modules
+            .iter()
+            .filter(|m| {
+                let module_type = ModuleType::from(m.type_field.as_str());
+                matches!(m.modules_bridged, Some(ref bridged) if !bridged.is_empty())
+                    || matches!(
+                        module_type,
+                        ModuleType::Relay | ModuleType::OpenThermRelay | ModuleType::LegrandGateway
+                    )
+            })
+            .map(|m| {
+                let module_status = status.modules.iter().find(|x| x.id == m.id);
+                GatewayHealth {
+                    id: m.id.clone(),
+                    name: m.name.clone(),
+                    module_type: ModuleType::from(m.type_field.as_str()),
+                    firmware_revision: module_status.map(|x| x.firmware_revision),
+                    wifi_strength: module_status.and_then(|x| x.wifi_strength),
+                    reachable: module_status.and_then(|x| x.reachable),
+                    modules_bridged: m.modules_bridged.clone().unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+
+    /// The module installed first, e.g. to check warranties; modules with unknown setup date are skipped
+    pub fn oldest_module(&self) -> Option<&Module> {
+        self.modules
+            .iter()
+            .filter(|x| x.setup_date > 0)
+            .min_by_key(|x| x.setup_date)
+    }
+
+    /// The module installed last; modules with unknown setup date are skipped
+    pub fn newest_module(&self) -> Option<&Module> {
+        self.modules
+            .iter()
+            .filter(|x| x.setup_date > 0)
+            .max_by_key(|x| x.setup_date)
+    }
+
+    /// The heating schedules of this home, i.e. skipping cooling, electricity, and event schedules
+    pub fn heating_schedules(&self) -> impl Iterator<Item = &Schedule> {
+        self.schedules.iter().filter(|x| x.type_field == ScheduleType::Therm)
+    }
+}
+
+/// The firmware and connectivity of a gateway, cf. `Home::gateway_health`
+#[derive(Debug, Clone, PartialEq)]
+pub struct GatewayHealth {
     pub id: String,
-    pub therm_setpoint_temperature: f64,
+    pub name: String,
+    pub module_type: ModuleType,
+    pub firmware_revision: Option<i64>,
+    pub wifi_strength: Option<i64>,
+    pub reachable: Option<bool>,
+    /// The ids of the modules connected to Netatmo through this gateway
+    pub modules_bridged: Vec<String>,
+}
+
+/// Timetable offsets count the minutes since Monday 00:00
+const MINUTES_PER_WEEK: i64 = 7 * 24 * 60;
+
+impl Schedule {
+    /// Checks the schedule for errors the API would reject it for
+    ///
+    /// The timetable offsets must be strictly ascending and within a week, and every entry must reference a defined
+    /// zone. Zones of any type may be defined, e.g. schedules of holiday homes may only consist of a frost guard zone.
+    pub fn validate(&self) -> Result<()> {
+        let mut previous_offset = None;
+        for (i, entry) in self.timetable.iter().enumerate() {
+            if entry.m_offset < 0 || entry.m_offset >= MINUTES_PER_WEEK {
+                return invalid_schedule(format!(
+                    "timetable entry {} has m_offset {} outside of 0..{}",
+                    i, entry.m_offset, MINUTES_PER_WEEK
+                ));
+            }
+            if matches!(previous_offset, Some(x) if x >= entry.m_offset) {
+                return invalid_schedule(format!(
+                    "timetable entry {} with m_offset {} is not in ascending order",
+                    i, entry.m_offset
+                ));
+            }
+            if !self.zones.iter().any(|z| z.id == entry.zone_id) {
+                return invalid_schedule(format!(
+                    "timetable entry {} references undefined zone {}",
+                    i, entry.zone_id
+                ));
+            }
+            previous_offset = Some(entry.m_offset);
+        }
+
+        Ok(())
+    }
+}
+
+/// The changes between two versions of a schedule, cf. `Schedule::diff`
+#[derive(Default, Debug, Clone, PartialEq)]
+pub struct ScheduleDiff {
+    pub added_zones: Vec<Zone>,
+    pub removed_zones: Vec<Zone>,
+    /// Zones with the same id, but a different name, type, or temperatures as pairs of old and new zone
+    pub changed_zones: Vec<(Zone, Zone)>,
+    pub added_timetable_entries: Vec<Timetable>,
+    pub removed_timetable_entries: Vec<Timetable>,
+    /// The old and new away temperature if changed
+    pub away_temp: Option<(i64, i64)>,
+    /// The old and new frost guard temperature if changed
+    pub hg_temp: Option<(i64, i64)>,
+}
+
+impl ScheduleDiff {
+    pub fn is_empty(&self) -> bool {
+        *self == ScheduleDiff::default()
+    }
+}
+
+impl Schedule {
+    /// The changes from this schedule to `other`, e.g. to preview the effect of syncing an edited schedule
+    pub fn diff(&self, other: &Schedule) -> ScheduleDiff {
+        let zone = |zones: &[Zone], id| zones.iter().find(|z| z.id == id).cloned();
+
+        let added_zones = other
+            .zones
+            .iter()
+            .filter(|z| zone(&self.zones, z.id).is_none())
+            .cloned()
+            .collect();
+        let mut removed_zones = Vec::new();
+        let mut changed_zones = Vec::new();
+        for old in &self.zones {
+            match zone(&other.zones, old.id) {
+                Some(new) if new != *old => changed_zones.push((old.clone(), new)),
+                Some(_) => {}
+                None => removed_zones.push(old.clone()),
+            }
+        }
+        let added_timetable_entries = other
+            .timetable
+            .iter()
+            .filter(|x| !self.timetable.contains(x))
+            .cloned()
+            .collect();
+        let removed_timetable_entries = self
+            .timetable
+            .iter()
+            .filter(|x| !other.timetable.contains(x))
+            .cloned()
+            .collect();
+        let changed = |old: i64, new: i64| if old != new { Some((old, new)) } else { None };
+
+        ScheduleDiff {
+            added_zones,
+            removed_zones,
+            changed_zones,
+            added_timetable_entries,
+            removed_timetable_entries,
+            away_temp: changed(self.away_temp, other.away_temp),
+            hg_temp: changed(self.hg_temp, other.hg_temp),
+        }
+    }
+}
+
+fn invalid_schedule(reason: String) -> Result<()> {
+    Err(ErrorKind::InvalidSchedule { reason }.into())
 }
 
 #[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -185,6 +530,289 @@ impl<'a> From<&'a Parameters<'a>> for HashMap<&str, String> {
 
 pub(crate) fn get_homes_data(client: &AuthenticatedClient, parameters: &Parameters) -> Result<HomesData> {
     let params: HashMap<&str, String> = parameters.into();
-    let mut params = params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
-    client.call("get_homes_data", "https://api.netatmo.com/api/homesdata", &mut params)
+    let params = params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+    client.call("get_homes_data", "/api/homesdata", &params)
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    mod schedule {
+        use super::*;
+
+        fn zone(id: i64, type_field: ZoneType) -> Zone {
+            Zone {
+                id,
+                type_field,
+                ..Zone::default()
+            }
+        }
+
+        fn schedule(timetable: &[(i64, i64)]) -> Schedule {
+            Schedule {
+                timetable: timetable
+                    .iter()
+                    .map(|&(zone_id, m_offset)| Timetable { zone_id, m_offset })
+                    .collect(),
+                zones: vec![zone(0, ZoneType::Comfort), zone(1, ZoneType::Night)],
+                ..Schedule::default()
+            }
+        }
+
+        fn reason(schedule: &Schedule) -> Option<String> {
+            match schedule.validate().map_err(|e| e.kind().clone()) {
+                Err(ErrorKind::InvalidSchedule { reason }) => Some(reason),
+                _ => None,
+            }
+        }
+
+        #[test]
+        fn valid() {
+            let schedule = schedule(&[(1, 0), (0, 420), (1, 1320)]);
+
+            assert_that(&schedule.validate()).is_ok();
+        }
+
+        #[test]
+        fn offset_out_of_range() {
+            let schedule = schedule(&[(1, 0), (0, MINUTES_PER_WEEK)]);
+
+            assert_that(&reason(&schedule)).is_equal_to(Some(
+                "timetable entry 1 has m_offset 10080 outside of 0..10080".to_string(),
+            ));
+        }
+
+        #[test]
+        fn offsets_not_ascending() {
+            let schedule = schedule(&[(1, 0), (0, 420), (1, 420)]);
+
+            assert_that(&reason(&schedule)).is_equal_to(Some(
+                "timetable entry 2 with m_offset 420 is not in ascending order".to_string(),
+            ));
+        }
+
+        #[test]
+        fn undefined_zone() {
+            let schedule = schedule(&[(1, 0), (4, 420)]);
+
+            assert_that(&reason(&schedule))
+                .is_equal_to(Some("timetable entry 1 references undefined zone 4".to_string()));
+        }
+
+        #[test]
+        fn diff() {
+            let old = schedule(&[(1, 0), (0, 420), (1, 1320)]);
+            let mut new = schedule(&[(1, 0), (0, 480), (1, 1320)]);
+            new.zones[0].name = "Comfort".to_string();
+            new.zones.push(zone(4, ZoneType::Custom));
+            new.hg_temp = 7;
+
+            let diff = old.diff(&new);
+
+            assert_that(&diff).is_equal_to(ScheduleDiff {
+                added_zones: vec![zone(4, ZoneType::Custom)],
+                removed_zones: vec![],
+                changed_zones: vec![(old.zones[0].clone(), new.zones[0].clone())],
+                added_timetable_entries: vec![Timetable {
+                    zone_id: 0,
+                    m_offset: 480,
+                }],
+                removed_timetable_entries: vec![Timetable {
+                    zone_id: 0,
+                    m_offset: 420,
+                }],
+                away_temp: None,
+                hg_temp: Some((0, 7)),
+            });
+            assert_that(&old.diff(&old).is_empty()).is_true();
+        }
+
+        #[test]
+        fn zones_of_any_type() {
+            let mut schedule = schedule(&[(3, 0)]);
+            schedule.zones = vec![zone(3, ZoneType::FrostGuard)];
+
+            assert_that(&schedule.validate()).is_ok();
+        }
+    }
+
+    mod zone {
+        use super::*;
+
+        #[test]
+        fn parse_both_setpoint_keys() {
+            let json = r#"[
+  {"name": "Comfort", "id": 0, "type": 0, "rooms_temp": [{"room_id": "1234", "temp": 21}]},
+  {"name": "Night", "id": 1, "type": 1, "rooms": [{"id": "1234", "therm_setpoint_temperature": 17}]},
+  {"name": "Eco", "id": 4, "type": 5, "rooms": [{"id": "1234"}]}
+]"#;
+
+            let zones: Vec<Zone> = serde_json::from_str(json).expect("failed to parse zones");
+
+            let setpoint = |zone: &Zone| {
+                zone.room_setpoints()
+                    .iter()
+                    .map(|r| (r.id.clone(), r.setpoint))
+                    .collect()
+            };
+            assert_that(&zones.iter().map(|z| z.type_field).collect::<Vec<_>>()).is_equal_to(vec![
+                ZoneType::Comfort,
+                ZoneType::Night,
+                ZoneType::Eco,
+            ]);
+            assert_that::<Vec<_>>(&setpoint(&zones[0])).is_equal_to(vec![("1234".to_string(), Some(21.0))]);
+            assert_that::<Vec<_>>(&setpoint(&zones[1])).is_equal_to(vec![("1234".to_string(), Some(17.0))]);
+            assert_that::<Vec<_>>(&setpoint(&zones[2])).is_equal_to(vec![("1234".to_string(), None)]);
+        }
+
+        #[test]
+        fn serialize_setpoint() {
+            let room = RoomSetpoint {
+                id: "1234".to_string(),
+                setpoint: Some(21.0),
+            };
+
+            let json = serde_json::to_string(&room).expect("failed to serialize room");
+
+            assert_that(&json.as_str()).is_equal_to(r#"{"id":"1234","therm_setpoint_temperature":21.0}"#);
+        }
+    }
+
+    mod home {
+        use super::*;
+
+        #[test]
+        fn parse_partial_response() {
+            let json = r#"{
+  "body": {
+    "homes": [
+      {
+        "id": "home_ok",
+        "name": "Home",
+        "timezone": "Europe/Paris",
+        "rooms": [],
+        "modules": [],
+        "therm_schedules": [],
+        "therm_setpoint_default_duration": 180,
+        "therm_mode": "schedule",
+        "schedules": []
+      },
+      {
+        "id": "home_failed",
+        "name": "Holiday home"
+      }
+    ],
+    "user": {
+      "email": "user@example.com",
+      "language": "fr-FR",
+      "locale": "fr-FR",
+      "feel_like_algorithm": 0,
+      "unit_pressure": 0,
+      "unit_system": 0,
+      "unit_wind": 0,
+      "id": "12345678901234567890abcd"
+    }
+  },
+  "errors": [{"code": 6, "id": "home_failed"}],
+  "status": "ok",
+  "time_exec": 0.05,
+  "time_server": 1556451492
+}"#;
+
+            let homes_data: HomesData = serde_json::from_str(json).expect("failed to parse homes data");
+
+            let ids: Vec<_> = homes_data.body.homes.iter().map(|x| x.id.as_str()).collect();
+            assert_that(&ids).is_equal_to(vec!["home_ok"]);
+            assert_that(&homes_data.errors).is_equal_to(vec![HomeError {
+                code: 6,
+                id: "home_failed".to_string(),
+            }]);
+        }
+
+        #[test]
+        fn module_age() {
+            let module = |id: &str, setup_date| Module {
+                id: id.to_string(),
+                setup_date,
+                ..Module::default()
+            };
+            let home = Home {
+                modules: vec![
+                    module("valve", 1_500_000_000),
+                    module("relay", 0),
+                    module("thermostat", 1_550_000_000),
+                ],
+                ..Home::default()
+            };
+
+            assert_that(&home.oldest_module().map(|x| x.id.as_str())).is_equal_to(Some("valve"));
+            assert_that(&home.newest_module().map(|x| x.id.as_str())).is_equal_to(Some("thermostat"));
+            assert_that(&home.modules[0].age_at(1_500_086_400)).is_equal_to(Some(Duration::from_secs(86_400)));
+            assert_that(&home.modules[1].age_at(1_500_086_400)).is_none();
+            assert_that(&Home::default().oldest_module()).is_none();
+        }
+
+        #[test]
+        fn gateway_health() {
+            let home = Home {
+                modules: vec![
+                    Module {
+                        id: "relay".to_string(),
+                        type_field: "NAPlug".to_string(),
+                        name: "Relay".to_string(),
+                        modules_bridged: Some(vec!["thermostat".to_string()]),
+                        ..Module::default()
+                    },
+                    Module {
+                        id: "thermostat".to_string(),
+                        type_field: "NATherm1".to_string(),
+                        bridge: Some("relay".to_string()),
+                        ..Module::default()
+                    },
+                    Module {
+                        id: "gateway".to_string(),
+                        type_field: "NLG".to_string(),
+                        ..Module::default()
+                    },
+                ],
+                ..Home::default()
+            };
+            let status = get_home_status::Home {
+                modules: vec![get_home_status::Module {
+                    id: "relay".to_string(),
+                    firmware_revision: 254,
+                    wifi_strength: Some(60),
+                    reachable: Some(true),
+                    ..get_home_status::Module::default()
+                }],
+                ..get_home_status::Home::default()
+            };
+
+            let health = home.gateway_health(&status);
+
+            assert_that(&health).is_equal_to(vec![
+                GatewayHealth {
+                    id: "relay".to_string(),
+                    name: "Relay".to_string(),
+                    module_type: ModuleType::Relay,
+                    firmware_revision: Some(254),
+                    wifi_strength: Some(60),
+                    reachable: Some(true),
+                    modules_bridged: vec!["thermostat".to_string()],
+                },
+                GatewayHealth {
+                    id: "gateway".to_string(),
+                    name: String::new(),
+                    module_type: ModuleType::LegrandGateway,
+                    firmware_revision: None,
+                    wifi_strength: None,
+                    reachable: None,
+                    modules_bridged: vec![],
+                },
+            ]);
+        }
+    }
 }
@@ -1,7 +1,58 @@
-use crate::{client::AuthenticatedClient, errors::Result};
+use crate::{
+    client::{
+        get_station_data::{unix_now, Device, Module},
+        module_type::ModuleType,
+        transport::cache_key,
+        AuthenticatedClient,
+    },
+    errors::{Error, ErrorContext, ErrorKind, Result},
+};
 
+use log::warn;
 use serde::{Deserialize, Deserializer, Serialize};
-use std::{collections::HashMap, fmt, str::FromStr};
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt,
+    io::{BufRead, BufReader, Read, Write},
+    ops::{Range, RangeInclusive},
+    str::FromStr,
+    thread,
+    time::Duration,
+};
+
+/// The maximum number of readings Netatmo returns per call
+pub const MAX_LIMIT: usize = 1024;
+
+/// The id of a single station or module, cf. `Parameters::single_module`
+///
+/// Unlike a plain `&str`, it cannot hold a list of ids: `ModuleId::new` rejects ids joined by commas, and the ids of
+/// the stations and modules of `get_station_data` are single ids anyway.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ModuleId<'a>(&'a str);
+
+impl<'a> ModuleId<'a> {
+    /// Fails with `ErrorKind::InvalidParameter` if `id` lists several ids
+    pub fn new(id: &'a str) -> Result<Self> {
+        check_single_id("module_id", id)?;
+        Ok(ModuleId(id))
+    }
+
+    pub fn as_str(&self) -> &'a str {
+        self.0
+    }
+}
+
+impl<'a> From<&'a Device> for ModuleId<'a> {
+    fn from(device: &'a Device) -> Self {
+        ModuleId(&device.id)
+    }
+}
+
+impl<'a> From<&'a Module> for ModuleId<'a> {
+    fn from(module: &'a Module) -> Self {
+        ModuleId(&module.id)
+    }
+}
 
 pub struct Parameters<'a> {
     device_id: &'a str,
@@ -10,7 +61,7 @@ pub struct Parameters<'a> {
     types: &'a [Type],
     date_begin: Option<usize>,
     date_end: Option<usize>,
-    limit: Option<bool>,
+    limit: Option<usize>,
     real_time: Option<bool>,
 }
 
@@ -28,6 +79,18 @@ impl<'a> Parameters<'a> {
         }
     }
 
+    /// Requests the measures of the module `module` of the station `device`
+    ///
+    /// Netatmo returns the measures of one module per call, which `ModuleId` ensures at compile time; use
+    /// `get_measure_all_modules` to fetch several modules.
+    pub fn single_module(device: ModuleId<'a>, module: ModuleId<'a>, scale: Scale, types: &'a [Type]) -> Self {
+        Parameters::with_module_id(device.0, module.0, scale, types)
+    }
+
+    /// Requests the measures of the module `module_id` of the station `device_id`
+    ///
+    /// Netatmo returns the measures of one module per call; use `get_measure_all_modules` to fetch several modules.
+    /// Passing several ids joined by commas fails when calling `get_measure`, prefer `single_module` to rule that out.
     pub fn with_module_id(device_id: &'a str, module_id: &'a str, scale: Scale, types: &'a [Type]) -> Self {
         Parameters {
             device_id,
@@ -55,21 +118,78 @@ impl<'a> Parameters<'a> {
         }
     }
 
-    pub fn limit(self, limit: bool) -> Self {
+    /// Limits the number of returned readings; Netatmo returns at most `MAX_LIMIT` readings per call, so a higher
+    /// limit is clamped to it and logged as warning
+    ///
+    /// Without a limit, Netatmo silently truncates the response to `MAX_LIMIT` readings, too. Request the rest of the
+    /// range by starting the next call after the last returned reading.
+    pub fn limit(self, limit: usize) -> Self {
+        if limit > MAX_LIMIT {
+            warn!(
+                "Requested {} measures, but Netatmo returns at most {}; clamping limit",
+                limit, MAX_LIMIT
+            );
+        }
         Parameters {
-            limit: Some(limit),
+            limit: Some(limit.min(MAX_LIMIT)),
             ..self
         }
     }
 
+    /// Whether the timestamps of aggregated scales are the starts of the windows instead of their centers
+    ///
+    /// Netatmo timestamps aggregated values with the center of their window by default, cf.
+    /// https://dev.netatmo.com/apidocumentation/weather#getmeasure, and the readings keep the timestamps as returned.
     pub fn real_time(self, real_time: bool) -> Self {
         Parameters {
             real_time: Some(real_time),
             ..self
         }
     }
+
+    /// A stable key identifying the requested measures, as used for cached responses
+    ///
+    /// The key doesn't depend on the order the parameters were set in. The order of the types does matter, as it is
+    /// the order of the columns returned.
+    pub fn cache_key(&self) -> String {
+        let params: HashMap<&str, String> = self.into();
+        let params = params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+        cache_key("get_measure", &params)
+    }
+
+    fn validate(&self) -> Result<()> {
+        check_single_id("device_id", self.device_id)?;
+        check_single_id("module_id", self.module_id)
+    }
+
+    /// Checks the requested time range against the first point in time data is available from, e.g. the
+    /// `date_setup` of a station
+    ///
+    /// Netatmo doesn't fail for ranges without data, but silently returns no values for them. So this fails with
+    /// `ErrorKind::DataNotRetained` if the whole range predates `available_from`, and logs a warning if only its start
+    /// does. Besides, Netatmo retains fine scales for a limited time only; use `Measure::covered_range` to find out
+    /// which part of the range returned data.
+    pub fn check_data_available(&self, available_from: usize) -> Result<()> {
+        match (self.date_begin, self.date_end) {
+            (_, Some(date_end)) if date_end < available_from => Err(ErrorKind::DataNotRetained {
+                date_end,
+                available_from,
+            }
+            .into()),
+            (Some(date_begin), _) if date_begin < available_from => {
+                warn!(
+                    "Requested measures of '{}' from {}, but data is only available from {}",
+                    self.module_id, date_begin, available_from
+                );
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Scale {
     Max,
     Min30,
@@ -80,6 +200,22 @@ pub enum Scale {
     Month1,
 }
 
+impl Scale {
+    /// The length of the aggregation window of this scale; `None` for `Max`, which isn't aggregated, and `Month1`,
+    /// whose windows vary in length
+    pub fn step(self) -> Option<Duration> {
+        let secs = match self {
+            Scale::Max | Scale::Month1 => return None,
+            Scale::Min30 => 30 * 60,
+            Scale::Hour1 => 60 * 60,
+            Scale::Hours3 => 3 * 60 * 60,
+            Scale::Day1 => 24 * 60 * 60,
+            Scale::Week1 => 7 * 24 * 60 * 60,
+        };
+        Some(Duration::from_secs(secs))
+    }
+}
+
 impl fmt::Display for Scale {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let s = match self {
@@ -95,10 +231,69 @@ impl fmt::Display for Scale {
     }
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Type {
     Temperature,
     Humidity,
     CO2,
+    Pressure,
+    Noise,
+    Rain,
+    WindStrength,
+    WindAngle,
+    GustStrength,
+    GustAngle,
+    SumRain,
+    BoilerOn,
+    BoilerOff,
+    SumBoilerOn,
+    SumBoilerOff,
+}
+
+impl Type {
+    /// The physically plausible values of this type; values outside are sensor errors or sentinels
+    pub fn plausible_range(&self) -> RangeInclusive<f64> {
+        match self {
+            Type::Temperature => -80.0..=80.0,
+            Type::Humidity => 0.0..=100.0,
+            Type::CO2 => 0.0..=10_000.0,
+            Type::Pressure => 300.0..=1_100.0,
+            Type::Noise => 0.0..=150.0,
+            Type::Rain | Type::SumRain => 0.0..=1_000.0,
+            Type::WindStrength | Type::GustStrength => 0.0..=400.0,
+            Type::WindAngle | Type::GustAngle => 0.0..=360.0,
+            Type::BoilerOn | Type::BoilerOff | Type::SumBoilerOn | Type::SumBoilerOff => 0.0..=f64::MAX,
+        }
+    }
+
+    /// The decimals Netatmo's apps display values of this type with, cf. `Measure::rounded`
+    pub fn display_precision(&self) -> usize {
+        match self {
+            Type::Temperature | Type::Pressure | Type::Rain | Type::SumRain => 1,
+            Type::Humidity
+            | Type::CO2
+            | Type::Noise
+            | Type::WindStrength
+            | Type::WindAngle
+            | Type::GustStrength
+            | Type::GustAngle
+            | Type::BoilerOn
+            | Type::BoilerOff
+            | Type::SumBoilerOn
+            | Type::SumBoilerOff => 0,
+        }
+    }
+
+    /// Whether values of this type accumulate over the interval of a reading, like rain, instead of being sampled,
+    /// like temperature
+    ///
+    /// Cumulative values must be summed when combining readings; sampled values are averaged.
+    pub fn is_cumulative(&self) -> bool {
+        matches!(
+            self,
+            Type::Rain | Type::SumRain | Type::BoilerOn | Type::BoilerOff | Type::SumBoilerOn | Type::SumBoilerOff
+        )
+    }
 }
 
 impl fmt::Display for Type {
@@ -107,6 +302,18 @@ impl fmt::Display for Type {
             Type::Temperature => "Temperature",
             Type::Humidity => "Humidity",
             Type::CO2 => "CO2",
+            Type::Pressure => "Pressure",
+            Type::Noise => "Noise",
+            Type::Rain => "Rain",
+            Type::WindStrength => "WindStrength",
+            Type::WindAngle => "WindAngle",
+            Type::GustStrength => "GustStrength",
+            Type::GustAngle => "GustAngle",
+            Type::SumRain => "sum_rain",
+            Type::BoilerOn => "boileron",
+            Type::BoilerOff => "boileroff",
+            Type::SumBoilerOn => "sum_boiler_on",
+            Type::SumBoilerOff => "sum_boiler_off",
         };
         write!(f, "{}", s)
     }
@@ -145,33 +352,1310 @@ impl<'a> From<&'a Parameters<'a>> for HashMap<&str, String> {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Measure {
     status: String,
     time_exec: f64,
-    #[serde(rename = "body", deserialize_with = "de_body_values")]
-    values: HashMap<usize, Vec<Option<f64>>>,
+    /// The requested types in the order of the columns of `values`
+    #[serde(skip)]
+    types: Vec<Type>,
+    /// The requested scale; `None` if unknown, e.g. for a measure not returned by `get_measure`
+    #[serde(skip)]
+    scale: Option<Scale>,
+    /// Whether the timestamps of aggregated scales are the starts of the windows instead of their centers, cf.
+    /// `Parameters::real_time`
+    #[serde(skip)]
+    real_time: bool,
+    #[serde(rename = "body", default, deserialize_with = "de_body_values")]
+    values: BTreeMap<usize, Vec<Option<f64>>>,
+}
+
+/// A single row of a `Measure`, i.e. the values of all requested types at one point in time
+#[derive(Debug, Clone, PartialEq)]
+pub struct Reading {
+    pub timestamp: usize,
+    /// The values in the order of `Measure::types`
+    pub values: Vec<Option<f64>>,
+}
+
+/// How `Measure::resample` combines the values falling into the same bucket
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Resample {
+    /// The value closest to the grid point; buckets are centered on the grid points
+    Nearest,
+    /// The average of all values
+    Mean,
+    /// The latest value
+    Last,
+    /// The sum of all values
+    Sum,
+    /// The lowest value
+    Min,
+    /// The highest value
+    Max,
+    /// `Sum` for cumulative types and `Mean` for all others, cf. `Type::is_cumulative`
+    #[default]
+    Auto,
+}
+
+impl Resample {
+    fn combine(self, measurement_type: Option<&Type>, grid_point: usize, values: &[(usize, f64)]) -> Option<f64> {
+        match self {
+            Resample::Auto if matches!(measurement_type, Some(t) if t.is_cumulative()) => {
+                Resample::Sum.combine(measurement_type, grid_point, values)
+            }
+            Resample::Auto => Resample::Mean.combine(measurement_type, grid_point, values),
+            Resample::Nearest => values
+                .iter()
+                .min_by_key(|(t, _)| {
+                    if *t > grid_point {
+                        t - grid_point
+                    } else {
+                        grid_point - t
+                    }
+                })
+                .map(|(_, v)| *v),
+            Resample::Mean if values.is_empty() => None,
+            Resample::Mean => Some(values.iter().map(|(_, v)| v).sum::<f64>() / values.len() as f64),
+            Resample::Last => values.last().map(|(_, v)| *v),
+            Resample::Sum if values.is_empty() => None,
+            Resample::Sum => Some(values.iter().map(|(_, v)| v).sum()),
+            Resample::Min => values.iter().map(|(_, v)| *v).reduce(f64::min),
+            Resample::Max => values.iter().map(|(_, v)| *v).reduce(f64::max),
+        }
+    }
+}
+
+/// The calendar periods `Measure::aggregate` combines readings by
+#[cfg(feature = "chrono-tz")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregationBucket {
+    /// A day from local midnight to local midnight, which lasts 23 or 25 hours on days of DST transitions
+    Day,
+}
+
+/// The readings of one calendar period combined, cf. `Measure::aggregate`
+#[cfg(feature = "chrono-tz")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregatedReading {
+    /// The local date the period starts at
+    pub date: chrono::NaiveDate,
+    /// The start of the period in seconds since the epoch
+    pub start: usize,
+    /// The combined values in the order of `Measure::types`
+    pub values: Vec<Option<f64>>,
+}
+
+/// A fixed order of measurement types to consistently interpret the readings of repeatedly fetched measures
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeasureSchema {
+    types: Vec<Type>,
+}
+
+impl MeasureSchema {
+    pub fn new(types: &[Type]) -> Self {
+        MeasureSchema { types: types.to_vec() }
+    }
+
+    pub fn types(&self) -> &[Type] {
+        &self.types
+    }
+
+    /// The index of `measurement_type` in the values of readings created by `Measure::into_readings_with`
+    pub fn column(&self, measurement_type: &Type) -> Option<usize> {
+        self.types.iter().position(|x| x == measurement_type)
+    }
+}
+
+impl Measure {
+    /// The measurement types of this measure in the order of `Reading::values`
+    pub fn types(&self) -> &[Type] {
+        &self.types
+    }
+
+    /// The index of `measurement_type` in `Reading::values`
+    pub fn column(&self, measurement_type: &Type) -> Option<usize> {
+        self.types.iter().position(|x| x == measurement_type)
+    }
+
+    /// Writes the readings as CSV with a header row of "timestamp" followed by the types; missing values are empty
+    ///
+    /// Values are written with the shortest representation that parses back to the same value, so `from_csv` restores
+    /// the readings without loss.
+    pub fn to_csv<W: Write>(&self, mut w: W) -> Result<()> {
+        let header: Vec<_> = std::iter::once("timestamp".to_string())
+            .chain(self.types.iter().map(Type::to_string))
+            .collect();
+        writeln!(w, "{}", header.join(",")).map_err(|e| e.context(ErrorKind::CsvAccessFailed))?;
+        let mut res = Ok(());
+        self.for_each_reading(|timestamp, values| {
+            if res.is_err() {
+                return;
+            }
+            let cells: Vec<_> = values
+                .iter()
+                .map(|x| x.map(|x| x.to_string()).unwrap_or_default())
+                .collect();
+            res = writeln!(w, "{},{}", timestamp, cells.join(","));
+        });
+
+        res.map_err(|e| e.context(ErrorKind::CsvAccessFailed))
+    }
+
+    /// Reads readings written by `to_csv`; the header row must list `types` in this order
+    ///
+    /// The timestamps are taken as they are, so the readings of the returned measure equal the exported ones.
+    pub fn from_csv<R: Read>(r: R, types: &[Type]) -> Result<Measure> {
+        let invalid = |line, reason: String| Error::from(ErrorKind::InvalidCsv { line, reason });
+        let mut lines = BufReader::new(r).lines();
+
+        let expected: Vec<_> = std::iter::once("timestamp".to_string())
+            .chain(types.iter().map(Type::to_string))
+            .collect();
+        let header = lines
+            .next()
+            .ok_or_else(|| invalid(1, "header is missing".to_string()))?
+            .map_err(|e| e.context(ErrorKind::CsvAccessFailed))?;
+        if header.trim_end().split(',').ne(expected.iter().map(String::as_str)) {
+            return Err(invalid(1, format!("expected header '{}'", expected.join(","))));
+        }
+
+        let mut values = BTreeMap::new();
+        for (i, line) in lines.enumerate() {
+            let number = i + 2;
+            let line = line.map_err(|e| e.context(ErrorKind::CsvAccessFailed))?;
+            let line = line.trim_end();
+            if line.is_empty() {
+                continue;
+            }
+            let mut cells = line.split(',');
+            let timestamp = cells
+                .next()
+                .and_then(|x| usize::from_str(x).ok())
+                .ok_or_else(|| invalid(number, "invalid timestamp".to_string()))?;
+            let row = cells
+                .map(|x| match x {
+                    "" => Ok(None),
+                    x => f64::from_str(x)
+                        .map(Some)
+                        .map_err(|_| invalid(number, format!("invalid value '{}'", x))),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if row.len() != types.len() {
+                return Err(invalid(
+                    number,
+                    format!("expected {} values, found {}", types.len(), row.len()),
+                ));
+            }
+            values.insert(timestamp, row);
+        }
+
+        Ok(Measure {
+            status: "ok".to_string(),
+            time_exec: 0.0,
+            types: types.to_vec(),
+            scale: None,
+            real_time: false,
+            values,
+        })
+    }
+
+    /// Whether Netatmo returned no readings, i.e. the requested range contains no data
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// The timestamps of the first and the last reading; `None` if this measure contains no readings
+    ///
+    /// This is the part of the requested range Netatmo returned data for, cf. `Parameters::check_data_available`.
+    pub fn covered_range(&self) -> Option<(usize, usize)> {
+        match (self.values.keys().next(), self.values.keys().next_back()) {
+            (Some(first), Some(last)) => Some((*first, *last)),
+            _ => None,
+        }
+    }
+
+    /// Calls `f` with the timestamp and values of every reading, ordered by timestamp
+    ///
+    /// In contrast to `into_readings`, this borrows the values and doesn't allocate, which matters when processing lots
+    /// of history on constrained devices. Use `column` to get the index of a type in the values.
+    pub fn for_each_reading<F>(&self, mut f: F)
+    where
+        F: FnMut(usize, &[Option<f64>]),
+    {
+        for (timestamp, values) in &self.values {
+            f(*timestamp, values);
+        }
+    }
+
+    /// Returns all readings, ordered by timestamp
+    pub fn into_readings(self) -> Vec<Reading> {
+        self.values
+            .into_iter()
+            .map(|(timestamp, values)| Reading { timestamp, values })
+            .collect()
+    }
+
+    /// Returns one time series per type, e.g. to plot each type as a separate line
+    ///
+    /// Each series contains every timestamp of `into_readings`, ordered by timestamp, with `None` where the type has
+    /// no value at that timestamp.
+    pub fn into_series(self) -> HashMap<Type, Vec<(usize, Option<f64>)>> {
+        let types = self.types.clone();
+        let readings = self.into_readings();
+        types
+            .into_iter()
+            .enumerate()
+            .map(|(column, measurement_type)| {
+                let series = readings
+                    .iter()
+                    .map(|r| (r.timestamp, r.values.get(column).cloned().flatten()))
+                    .collect();
+                (measurement_type, series)
+            })
+            .collect()
+    }
+
+    /// Returns all readings, ordered by timestamp, with their values in the order of `schema`
+    ///
+    /// Fails if the measure lacks any of the types of `schema`.
+    pub fn into_readings_with(self, schema: &MeasureSchema) -> Result<Vec<Reading>> {
+        let columns = schema
+            .types
+            .iter()
+            .map(|t| self.column(t).ok_or(ErrorKind::MeasureTypesMismatch))
+            .collect::<::std::result::Result<Vec<_>, _>>()?;
+        let readings = self
+            .values
+            .into_iter()
+            .map(|(timestamp, values)| Reading {
+                timestamp,
+                values: columns.iter().map(|&i| values.get(i).cloned().flatten()).collect(),
+            })
+            .collect();
+
+        Ok(readings)
+    }
+
+    /// Replaces values outside of the plausible range of their type by `None`, cf. `Type::plausible_range`
+    ///
+    /// Missing values are always `None`, but this filtering of implausible values is opt-in.
+    pub fn sanitize(mut self) -> Self {
+        let ranges: Vec<_> = self.types.iter().map(Type::plausible_range).collect();
+        for values in self.values.values_mut() {
+            for (value, range) in values.iter_mut().zip(&ranges) {
+                if matches!(value, Some(x) if !range.contains(x)) {
+                    *value = None;
+                }
+            }
+        }
+
+        self
+    }
+
+    /// Rounds the values to the display precision of their type, cf. `Type::display_precision`, e.g. for cleaner CSV
+    /// or JSON exports
+    pub fn rounded(self) -> Self {
+        self.rounded_with(Type::display_precision)
+    }
+
+    /// Rounds the values to the decimals `precision` returns for their type
+    pub fn rounded_with<F>(mut self, precision: F) -> Self
+    where
+        F: Fn(&Type) -> usize,
+    {
+        let factors: Vec<_> = self.types.iter().map(|x| 10f64.powi(precision(x) as i32)).collect();
+        for values in self.values.values_mut() {
+            for (value, factor) in values.iter_mut().zip(&factors) {
+                *value = value.map(|x| (x * factor).round() / factor);
+            }
+        }
+
+        self
+    }
+
+    /// Buckets the readings onto a regular grid of `interval`, e.g. to align the measures of different modules
+    ///
+    /// The grid points are multiples of `interval` since the epoch, and each bucket spans from its grid point to the next
+    /// one; except for `Resample::Nearest` whose buckets are centered on the grid points. Each value of a bucket is
+    /// combined from the values of its type independently, and is `None` if no value of its type falls into the bucket.
+    pub fn resample(&self, interval: Duration, method: Resample) -> Vec<Reading> {
+        let step = interval.as_secs() as usize;
+        let (first, last) = match (self.values.keys().next(), self.values.keys().next_back()) {
+            (Some(first), Some(last)) if step > 0 => (*first, *last),
+            _ => return Vec::new(),
+        };
+        let offset = if method == Resample::Nearest { step / 2 } else { 0 };
+        let columns = self.values.values().map(Vec::len).max().unwrap_or_default();
+
+        let mut readings = Vec::new();
+        let mut grid_point = (first + offset) / step * step;
+        while grid_point <= last + offset {
+            let begin = grid_point.saturating_sub(offset);
+            let bucket: Vec<_> = self.values.range(begin..begin + step).collect();
+            let values = (0..columns)
+                .map(|column| {
+                    let values: Vec<_> = bucket
+                        .iter()
+                        .filter_map(|(t, values)| values.get(column).cloned().flatten().map(|v| (**t, v)))
+                        .collect();
+                    method.combine(self.types.get(column), grid_point, &values)
+                })
+                .collect();
+            readings.push(Reading {
+                timestamp: grid_point,
+                values,
+            });
+            grid_point += step;
+        }
+
+        readings
+    }
+
+    /// Combines the readings by calendar period in the time zone `tz`, e.g. to get the daily low temperatures
+    ///
+    /// Periods without readings are left out. Use the time zone of the station, cf. `get_station_data::Place::tz`, so
+    /// readings near midnight land in the day the user expects.
+    #[cfg(feature = "chrono-tz")]
+    pub fn aggregate(&self, bucket: AggregationBucket, tz: chrono_tz::Tz, method: Resample) -> Vec<AggregatedReading> {
+        use chrono::TimeZone;
+
+        let AggregationBucket::Day = bucket;
+        let mut days: BTreeMap<_, Vec<_>> = BTreeMap::new();
+        for (&timestamp, values) in &self.values {
+            if let Some(local) = tz.timestamp_opt(timestamp as i64, 0).single() {
+                days.entry(local.date_naive())
+                    .or_default()
+                    .push((timestamp, values.as_slice()));
+            }
+        }
+
+        let columns = self.values.values().map(Vec::len).max().unwrap_or_default();
+        days.into_iter()
+            .map(|(date, readings)| {
+                // Some time zones skip midnight when switching to DST, so the day starts at the first existing instant
+                let start = (0..24)
+                    .filter_map(|hour| date.and_hms_opt(hour, 0, 0))
+                    .find_map(|x| tz.from_local_datetime(&x).earliest())
+                    .map_or(readings[0].0, |x| x.timestamp() as usize);
+                let values = (0..columns)
+                    .map(|column| {
+                        let values: Vec<_> = readings
+                            .iter()
+                            .filter_map(|(t, values)| values.get(column).cloned().flatten().map(|v| (*t, v)))
+                            .collect();
+                        method.combine(self.types.get(column), start, &values)
+                    })
+                    .collect();
+                AggregatedReading { date, start, values }
+            })
+            .collect()
+    }
+
+    /// Appends the readings of `other` to this measure, e.g. when a long time range has been fetched in chunks.
+    ///
+    /// Both measures must consist of the same types in the same order and, if known, of the same scale and
+    /// `real_time` option. If both measures contain a reading for the same timestamp, which is typically the case at
+    /// the boundary of two consecutive chunks, the reading of `self` is kept.
+    pub fn extend(&mut self, other: Measure) -> Result<()> {
+        if self.types != other.types {
+            return Err(ErrorKind::MeasureTypesMismatch.into());
+        }
+        if self.scale.is_some()
+            && other.scale.is_some()
+            && (self.scale, self.real_time) != (other.scale, other.real_time)
+        {
+            return Err(ErrorKind::MeasureScalesMismatch.into());
+        }
+        for (timestamp, values) in other.values {
+            self.values.entry(timestamp).or_insert(values);
+        }
+
+        Ok(())
+    }
+}
+
+/// The measures of a weather station and its modules, cf. `get_measure_all_modules`
+#[derive(Debug)]
+pub struct StationMeasures {
+    pub modules: Vec<ModuleMeasure>,
+}
+
+/// The result of fetching the measure of a single module
+#[derive(Debug)]
+pub struct ModuleMeasure {
+    pub module_id: String,
+    pub measure: Result<Measure>,
+}
+
+impl StationMeasures {
+    /// The measures of all modules that could be fetched
+    pub fn succeeded(&self) -> impl Iterator<Item = (&str, &Measure)> {
+        self.modules
+            .iter()
+            .filter_map(|x| x.measure.as_ref().ok().map(|m| (x.module_id.as_str(), m)))
+    }
+
+    /// The errors of all modules that could not be fetched
+    pub fn failed(&self) -> impl Iterator<Item = (&str, &Error)> {
+        self.modules
+            .iter()
+            .filter_map(|x| x.measure.as_ref().err().map(|e| (x.module_id.as_str(), e)))
+    }
+
+    /// Whether the measures of all modules could be fetched
+    pub fn is_complete(&self) -> bool {
+        self.modules.iter().all(|x| x.measure.is_ok())
+    }
 }
 
 // cf. https://dev.netatmo.com/resources/technical/reference/common/getmeasure
 pub fn get_measure(client: &AuthenticatedClient, parameters: &Parameters) -> Result<Measure> {
+    parameters.validate()?;
     let params: HashMap<&str, String> = parameters.into();
-    let mut params = params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+    let params = params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+    let mut measure: Measure = client.call("get_measure", "/api/getmeasure", &params)?;
+    measure.types = parameters.types.to_vec();
+    measure.scale = Some(parameters.scale);
+    measure.real_time = parameters.real_time.unwrap_or(false);
+
+    Ok(measure)
+}
+
+/// Fetches the measures of the station `device` and each of its modules with the scale, time range, and options of
+/// `parameters`
+///
+/// Each module is only asked for the requested types it supports, cf. `ModuleType::measurement_capabilities`, and
+/// modules supporting none of them are skipped. A failure to fetch one module doesn't affect the others. Modules are not
+/// asked for time ranges before the station's setup, cf. `Parameters::check_data_available`.
+pub fn get_measure_all_modules(
+    client: &AuthenticatedClient,
+    device: &Device,
+    parameters: &Parameters,
+) -> StationMeasures {
+    let station = (&device.id, &device.type_info);
+    let modules = device.modules.iter().map(|m| (&m.id, &m.type_info));
+
+    let modules = std::iter::once(station)
+        .chain(modules)
+        .filter_map(|(module_id, type_info)| {
+            let module_type = ModuleType::from(type_info.as_str());
+            let types: Vec<Type> = parameters
+                .types
+                .iter()
+                .filter(|t| module_type.supports(t))
+                .cloned()
+                .collect();
+            if types.is_empty() {
+                return None;
+            }
+            let module_parameters = Parameters {
+                device_id: &device.id,
+                module_id,
+                types: &types,
+                ..*parameters
+            };
+            let measure = module_parameters
+                .check_data_available(device.date_setup as usize)
+                .and_then(|_| get_measure(client, &module_parameters));
+            Some(ModuleMeasure {
+                module_id: module_id.clone(),
+                measure,
+            })
+        })
+        .collect();
+
+    StationMeasures { modules }
+}
+
+/// Fails if `id` lists several ids joined by commas, which `getmeasure` doesn't support
+fn check_single_id(name: &'static str, id: &str) -> Result<()> {
+    if id.contains(',') {
+        return Err(ErrorKind::InvalidParameter {
+            name,
+            reason: format!(
+                "'{}' lists several ids, but measures can only be fetched per module; use get_measure_all_modules \
+                 instead",
+                id
+            ),
+        }
+        .into());
+    }
+
+    Ok(())
+}
+
+const DAY: usize = 24 * 60 * 60;
+
+/// The scales of `history_adaptive`, coarsest first, with the age relative to the end of the range from which on a
+/// scale is used; the finest scale is used for the rest of the range
+const ADAPTIVE_SCALES: &[(Scale, usize)] = &[(Scale::Day1, 30 * DAY), (Scale::Hours3, 2 * DAY)];
+const ADAPTIVE_FINEST_SCALE: Scale = Scale::Min30;
 
-    client.call("get_measure", "https://api.netatmo.com/api/getmeasure", &mut params)
+/// Cf. `AuthenticatedClient::history_adaptive`
+///
+/// Netatmo aligns aggregation windows to the local time of the station, e.g. days start at the station's midnight. So
+/// the coarse parts are fetched with `real_time` to learn the starts of their windows, and each part ends at the start
+/// of its last window, whose readings are left to the next finer scale. This way no window is covered twice and
+/// summing cumulative types like rain over the parts yields consistent totals, whatever the time zone of the station.
+/// Timestamps are the centers of the windows, like Netatmo returns them without `real_time`.
+pub(crate) fn history_adaptive(
+    client: &AuthenticatedClient,
+    device_id: &str,
+    module_id: &str,
+    measurement_type: Type,
+    range: Range<usize>,
+) -> Result<Vec<Reading>> {
+    let types = [measurement_type];
+    let mut readings = Vec::new();
+    let mut begin = range.start;
+    for &(scale, age) in ADAPTIVE_SCALES {
+        let end = range.end.saturating_sub(age);
+        if end <= begin {
+            continue;
+        }
+        let step = scale.step().map_or(0, |x| x.as_secs() as usize);
+        let parameters = Parameters::with_module_id(device_id, module_id, scale, &types).real_time(true);
+        let mut windows = get_measure_between(client, &parameters, begin..end)?.into_readings();
+        // The last window may extend beyond `end`, so the next scale starts with it
+        if let Some(last) = windows.pop() {
+            begin = last.timestamp;
+        }
+        readings.extend(windows.into_iter().map(|x| Reading {
+            timestamp: x.timestamp + step / 2,
+            ..x
+        }));
+    }
+    if range.end > begin {
+        let parameters = Parameters::with_module_id(device_id, module_id, ADAPTIVE_FINEST_SCALE, &types);
+        readings.extend(get_measure_between(client, &parameters, begin..range.end)?.into_readings());
+    }
+
+    Ok(readings)
 }
 
-fn de_body_values<'de, D>(deserializer: D) -> ::std::result::Result<HashMap<usize, Vec<Option<f64>>>, D::Error>
+/// Fetches all measures within `range`, in multiple calls if there are more than `MAX_LIMIT`
+fn get_measure_between(client: &AuthenticatedClient, parameters: &Parameters, range: Range<usize>) -> Result<Measure> {
+    let mut measure: Option<Measure> = None;
+    let mut date_begin = range.start;
+    loop {
+        let page_parameters = Parameters {
+            date_begin: Some(date_begin),
+            date_end: Some(range.end - 1),
+            limit: Some(MAX_LIMIT),
+            ..*parameters
+        };
+        let mut page = get_measure(client, &page_parameters)?;
+        let complete = page.values.len() < MAX_LIMIT;
+        let last = page.values.keys().next_back().cloned();
+        page.values.retain(|timestamp, _| range.contains(timestamp));
+        match measure {
+            Some(ref mut measure) => measure.extend(page)?,
+            None => measure = Some(page),
+        }
+        match last {
+            Some(last) if !complete && last + 1 < range.end => date_begin = last + 1,
+            _ => break,
+        }
+    }
+
+    Ok(measure.expect("at least one page is fetched"))
+}
+
+/// Polls the newest raw measures of `measurement_type` of a module every `interval` and calls `on_data` with each
+/// reading not seen before, oldest first, until `on_data` returns `false` or a call fails
+///
+/// Polling starts with the readings of the last `interval`.
+pub(crate) fn measure_stream<F>(
+    client: &AuthenticatedClient,
+    device_id: &str,
+    module_id: &str,
+    measurement_type: Type,
+    interval: Duration,
+    mut on_data: F,
+) -> Result<()>
+where
+    F: FnMut(Reading) -> bool,
+{
+    let types = [measurement_type];
+    let mut date_begin = unix_now().saturating_sub(interval.as_secs()) as usize;
+    let mut last_timestamp = None;
+    loop {
+        let parameters = Parameters::with_module_id(device_id, module_id, Scale::Max, &types)
+            .real_time(true)
+            .date_begin(date_begin)
+            .limit(MAX_LIMIT);
+        let measure = get_measure(client, &parameters)?;
+        for reading in new_readings(measure, &mut last_timestamp) {
+            if !on_data(reading) {
+                return Ok(());
+            }
+        }
+        if let Some(last_timestamp) = last_timestamp {
+            date_begin = last_timestamp;
+        }
+        thread::sleep(interval);
+    }
+}
+
+/// The readings of `measure` newer than `last_timestamp`, which is advanced to the newest reading
+fn new_readings(measure: Measure, last_timestamp: &mut Option<usize>) -> Vec<Reading> {
+    let readings: Vec<_> = measure
+        .into_readings()
+        .into_iter()
+        .filter(|r| !matches!(*last_timestamp, Some(last) if r.timestamp <= last))
+        .collect();
+    if let Some(newest) = readings.last() {
+        *last_timestamp = Some(newest.timestamp);
+    }
+
+    readings
+}
+
+fn de_body_values<'de, D>(deserializer: D) -> ::std::result::Result<BTreeMap<usize, Vec<Option<f64>>>, D::Error>
 where
     D: Deserializer<'de>,
 {
-    let map = HashMap::<String, Vec<Option<f64>>>::deserialize(deserializer)?;
-    let mut tuples = Vec::new();
-    for (k, v) in map {
-        let key = usize::from_str(&k).map_err(serde::de::Error::custom)?;
-        tuples.push((key, v));
+    /// Netatmo returns values keyed by timestamp unless `optimize` is requested, but answers with an array of blocks
+    /// anyway in some cases, e.g. an empty array for a range without data or several blocks at scale `max` when a
+    /// device reported at irregular intervals
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Body {
+        Values(HashMap<String, Vec<Option<f64>>>),
+        Blocks(Vec<Block>),
+    }
+
+    /// Readings every `step_time` seconds starting at `beg_time`
+    #[derive(Deserialize)]
+    struct Block {
+        beg_time: usize,
+        step_time: Option<usize>,
+        value: Vec<Vec<Option<f64>>>,
+    }
+
+    let mut res = BTreeMap::new();
+    match Body::deserialize(deserializer)? {
+        Body::Values(map) => {
+            for (k, v) in map {
+                let key = usize::from_str(&k).map_err(serde::de::Error::custom)?;
+                res.insert(key, v);
+            }
+        }
+        Body::Blocks(blocks) => {
+            for block in blocks {
+                let step_time = match (block.step_time, block.value.len()) {
+                    (Some(step_time), _) => step_time,
+                    (None, 0) | (None, 1) => 0,
+                    (None, _) => {
+                        return Err(serde::de::Error::custom(format!(
+                            "block starting at {} with several values lacks step_time",
+                            block.beg_time
+                        )))
+                    }
+                };
+                for (i, values) in block.value.into_iter().enumerate() {
+                    res.insert(block.beg_time + i * step_time, values);
+                }
+            }
+        }
     }
-    let res = tuples.into_iter().collect();
 
     Ok(res)
 }
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+    use crate::client::transport::{replay_transcript, TranscriptEntry};
+
+    fn measure(types: &[Type], json: &str) -> Measure {
+        let mut measure: Measure = serde_json::from_str(json).expect("failed to parse measure");
+        measure.types = types.to_vec();
+        measure
+    }
+
+    /// A client replaying `responses` as statuses and bodies of consecutive `get_measure` calls
+    fn replay(name: &str, responses: &[(u16, String)]) -> AuthenticatedClient {
+        let entries: Vec<_> = responses
+            .iter()
+            .map(|(status, body)| TranscriptEntry::response("get_measure", *status, body))
+            .collect();
+        replay_transcript(name, &entries)
+    }
+
+    #[test]
+    fn cache_key_is_independent_of_parameter_order() {
+        let types = [Type::Temperature, Type::Humidity];
+        let first = Parameters::with_module_id("a_device", "a_module", Scale::Max, &types)
+            .date_begin(100)
+            .limit(10)
+            .date_end(200);
+        let second = Parameters::with_module_id("a_device", "a_module", Scale::Max, &types)
+            .date_end(200)
+            .limit(10)
+            .date_begin(100);
+        let reversed = [Type::Humidity, Type::Temperature];
+        // The columns are returned in the order of the types, so this is a different request
+        let reordered = Parameters::with_module_id("a_device", "a_module", Scale::Max, &reversed)
+            .date_begin(100)
+            .limit(10)
+            .date_end(200);
+
+        assert_that(&first.cache_key()).is_equal_to(second.cache_key());
+        assert_that(&first.cache_key()).is_equal_to(
+            "get_measure?date_begin=100&date_end=200&device_id=a_device&limit=10&module_id=a_module&optimize=false\
+             &scale=max&type=Temperature,Humidity"
+                .to_string(),
+        );
+        assert_that(&first.cache_key()).is_not_equal_to(reordered.cache_key());
+    }
+
+    #[test]
+    fn csv_round_trip() {
+        let types = [Type::Temperature, Type::Humidity];
+        let mut original = measure(
+            &types,
+            r#"{"status": "ok", "time_exec": 0.1, "body": {"100": [20.1, 43], "200": [null, 44], "300": [-0.30000000000000004, null]}}"#,
+        );
+        original.scale = Some(Scale::Min30);
+
+        let mut csv = Vec::new();
+        original.to_csv(&mut csv).expect("failed to export CSV");
+        let imported = Measure::from_csv(csv.as_slice(), &types).expect("failed to import CSV");
+
+        assert_that(&String::from_utf8(csv).unwrap()).is_equal_to(
+            "timestamp,Temperature,Humidity\n100,20.1,43\n200,,44\n300,-0.30000000000000004,\n".to_string(),
+        );
+        assert_that(&imported.into_readings()).is_equal_to(original.into_readings());
+    }
+
+    #[test]
+    fn rounded() {
+        let types = [Type::Temperature, Type::Humidity];
+        let original = measure(
+            &types,
+            r#"{"status": "ok", "time_exec": 0.1, "body": {"100": [20.149999, 43.5], "200": [-0.30000000000000004, null]}}"#,
+        );
+
+        let mut csv = Vec::new();
+        original
+            .clone()
+            .rounded()
+            .to_csv(&mut csv)
+            .expect("failed to export CSV");
+        let precise = original.rounded_with(|x| if *x == Type::Temperature { 3 } else { 1 });
+
+        assert_that(&String::from_utf8(csv).unwrap())
+            .is_equal_to("timestamp,Temperature,Humidity\n100,20.1,44\n200,-0.3,\n".to_string());
+        assert_that(&precise.into_readings()[0].values).is_equal_to(vec![Some(20.15), Some(43.5)]);
+    }
+
+    #[test]
+    fn csv_with_wrong_header() {
+        let csv = "timestamp,Humidity,Temperature\n100,43,20.1\n";
+
+        let res = Measure::from_csv(csv.as_bytes(), &[Type::Temperature, Type::Humidity]).map_err(|e| e.kind().clone());
+
+        assert_that(&res).is_err_containing(ErrorKind::InvalidCsv {
+            line: 1,
+            reason: "expected header 'timestamp,Temperature,Humidity'".to_string(),
+        });
+    }
+
+    #[test]
+    fn csv_with_missing_value() {
+        let csv = "timestamp,Temperature,Humidity\n100,20.1\n";
+
+        let res = Measure::from_csv(csv.as_bytes(), &[Type::Temperature, Type::Humidity]).map_err(|e| e.kind().clone());
+
+        assert_that(&res).is_err_containing(ErrorKind::InvalidCsv {
+            line: 2,
+            reason: "expected 2 values, found 1".to_string(),
+        });
+    }
+
+    #[test]
+    fn several_module_ids() {
+        let types = [Type::Temperature];
+        let parameters = Parameters::with_module_id("a_station", "a_module,another_module", Scale::Max, &types);
+
+        let res = parameters.validate().map_err(|e| e.kind().clone());
+
+        assert_that(&res).is_err_containing(ErrorKind::InvalidParameter {
+            name: "module_id",
+            reason: "'a_module,another_module' lists several ids, but measures can only be fetched per module; use \
+                     get_measure_all_modules instead"
+                .to_string(),
+        });
+        assert_that(&Parameters::new("a_station", Scale::Max, &types).validate()).is_ok();
+    }
+
+    #[test]
+    fn single_module() {
+        let types = [Type::Temperature];
+        let station = ModuleId::new("a_station").expect("failed to create module id");
+        let module = ModuleId::new("a_module").expect("failed to create module id");
+
+        let parameters = Parameters::single_module(station, module, Scale::Max, &types);
+        let params: HashMap<&str, String> = (&parameters).into();
+
+        assert_that(&params.get("module_id")).is_equal_to(Some(&"a_module".to_string()));
+        assert_that(&parameters.validate()).is_ok();
+        assert_that(&ModuleId::new("a_module,another_module").map_err(|e| e.kind().clone())).is_err_containing(
+            ErrorKind::InvalidParameter {
+                name: "module_id",
+                reason: "'a_module,another_module' lists several ids, but measures can only be fetched per module; \
+                         use get_measure_all_modules instead"
+                    .to_string(),
+            },
+        );
+    }
+
+    #[cfg(feature = "chrono-tz")]
+    #[test]
+    fn aggregate_by_local_day_across_dst() {
+        use chrono::{TimeZone, Utc};
+
+        let utc = |d, h, m| Utc.with_ymd_and_hms(2024, 3, d, h, m, 0).unwrap().timestamp();
+        // Paris switches from UTC+1 to UTC+2 on 2024-03-31, so the local day lasts 23 hours
+        let json = format!(
+            r#"{{"status": "ok", "time_exec": 0.1, "body": {{"{}": [4.0], "{}": [2.0], "{}": [7.5], "{}": [6.0], "{}": [3.0]}}}}"#,
+            utc(30, 22, 30),
+            utc(30, 23, 30),
+            utc(31, 12, 0),
+            utc(31, 21, 30),
+            utc(31, 22, 30),
+        );
+        let measure = measure(&[Type::Temperature], &json);
+
+        let lows = measure.aggregate(AggregationBucket::Day, chrono_tz::Europe::Paris, Resample::Min);
+
+        let date = |m, d| chrono::NaiveDate::from_ymd_opt(2024, m, d).unwrap();
+        assert_that(&lows).is_equal_to(vec![
+            AggregatedReading {
+                date: date(3, 30),
+                start: utc(29, 23, 0) as usize,
+                values: vec![Some(4.0)],
+            },
+            AggregatedReading {
+                date: date(3, 31),
+                start: utc(30, 23, 0) as usize,
+                values: vec![Some(2.0)],
+            },
+            AggregatedReading {
+                date: date(4, 1),
+                start: utc(31, 22, 0) as usize,
+                values: vec![Some(3.0)],
+            },
+        ]);
+    }
+
+    #[test]
+    fn parse_empty_bodies() {
+        for json in &[
+            r#"{"status": "ok", "time_exec": 0.1, "body": []}"#,
+            r#"{"status": "ok", "time_exec": 0.1, "body": {}}"#,
+            r#"{"status": "ok", "time_exec": 0.1}"#,
+        ] {
+            let measure: Measure = serde_json::from_str(json).expect("failed to parse empty measure");
+
+            assert_that(&measure.is_empty()).is_true();
+            assert_that(&measure.covered_range()).is_none();
+        }
+    }
+
+    #[test]
+    fn parse_blocks() {
+        let types = [Type::Temperature, Type::Humidity];
+        let measure = measure(
+            &types,
+            r#"{"status": "ok", "time_exec": 0.1, "body": [
+                {"beg_time": 1000, "step_time": 300, "value": [[20.1, 43], [20.2, 44], [20.3, null]]},
+                {"beg_time": 2500, "value": [[19.9, 45]]},
+                {"beg_time": 3000, "step_time": 600, "value": [[19.8, 46], [19.7, 47]]}
+            ]}"#,
+        );
+
+        let timestamps: Vec<_> = measure.clone().into_readings().iter().map(|x| x.timestamp).collect();
+
+        assert_that(&timestamps).is_equal_to(vec![1000, 1300, 1600, 2500, 3000, 3600]);
+        assert_that(&measure.into_readings()[2].values).is_equal_to(vec![Some(20.3), None]);
+    }
+
+    #[test]
+    fn parse_block_without_step_time_fails() {
+        let res = serde_json::from_str::<Measure>(
+            r#"{"status": "ok", "time_exec": 0.1, "body": [{"beg_time": 1000, "value": [[20.1], [20.2]]}]}"#,
+        );
+
+        assert_that(&res).is_err();
+    }
+
+    #[test]
+    fn parse_non_empty_array_body_fails() {
+        let res = serde_json::from_str::<Measure>(r#"{"status": "ok", "time_exec": 0.1, "body": [1]}"#);
+
+        assert_that(&res).is_err();
+    }
+
+    #[test]
+    fn extend_appends_and_dedupes_boundary() {
+        let types = [Type::Temperature, Type::Humidity];
+        let mut first = measure(
+            &types,
+            r#"{"status": "ok", "time_exec": 0.1, "body": {"100": [20.1, 43], "200": [20.2, 44]}}"#,
+        );
+        let second = measure(
+            &types,
+            r#"{"status": "ok", "time_exec": 0.1, "body": {"200": [99.9, 99], "300": [20.3, null]}}"#,
+        );
+
+        let res = first.extend(second);
+
+        assert_that(&res).is_ok();
+        let readings = first.into_readings();
+        assert_that(&readings).is_equal_to(vec![
+            Reading {
+                timestamp: 100,
+                values: vec![Some(20.1), Some(43.0)],
+            },
+            Reading {
+                timestamp: 200,
+                values: vec![Some(20.2), Some(44.0)],
+            },
+            Reading {
+                timestamp: 300,
+                values: vec![Some(20.3), None],
+            },
+        ]);
+    }
+
+    #[test]
+    fn into_readings_with_schema() {
+        let measure = measure(
+            &[Type::Temperature, Type::Humidity],
+            r#"{"status": "ok", "time_exec": 0.1, "body": {"100": [20.1, 43]}}"#,
+        );
+        let schema = MeasureSchema::new(&[Type::Humidity, Type::Temperature]);
+
+        let readings = measure.clone().into_readings_with(&schema);
+        let missing = measure.into_readings_with(&MeasureSchema::new(&[Type::CO2]));
+
+        assert_that(&readings).is_ok_containing(vec![Reading {
+            timestamp: 100,
+            values: vec![Some(43.0), Some(20.1)],
+        }]);
+        assert_that(&missing.map_err(|e| e.kind().clone())).is_err_containing(ErrorKind::MeasureTypesMismatch);
+    }
+
+    #[test]
+    fn into_readings_keeps_timestamps_of_aggregated_readings() {
+        let json = r#"{"status": "ok", "time_exec": 0.1, "body": {"5400": [20.1]}}"#;
+        let mut aggregated = measure(&[Type::Temperature], json);
+        aggregated.scale = Some(Scale::Hour1);
+
+        let timestamps = aggregated
+            .into_readings()
+            .iter()
+            .map(|r| r.timestamp)
+            .collect::<Vec<_>>();
+
+        assert_that(&timestamps).is_equal_to(vec![5400]);
+    }
+
+    #[test]
+    fn extend_rejects_other_scales() {
+        let json = r#"{"status": "ok", "time_exec": 0.1, "body": {"5400": [20.1]}}"#;
+        let mut hourly = measure(&[Type::Temperature], json);
+        hourly.scale = Some(Scale::Hour1);
+        let mut daily = hourly.clone();
+        daily.scale = Some(Scale::Day1);
+        let mut real_time = hourly.clone();
+        real_time.real_time = true;
+
+        let res = hourly.clone().extend(daily).map_err(|e| e.kind().clone());
+
+        assert_that(&res).is_err_containing(ErrorKind::MeasureScalesMismatch);
+        assert_that(&hourly.clone().extend(real_time).map_err(|e| e.kind().clone()))
+            .is_err_containing(ErrorKind::MeasureScalesMismatch);
+        assert_that(&hourly.clone().extend(measure(&[Type::Temperature], json))).is_ok();
+    }
+
+    #[test]
+    fn into_series() {
+        let measure = measure(
+            &[Type::Temperature, Type::Humidity],
+            r#"{"status": "ok", "time_exec": 0.1, "body": {"100": [20.1, null], "200": [20.2, 44]}}"#,
+        );
+
+        let series = measure.into_series();
+
+        assert_that(&series).has_length(2);
+        assert_that(&series[&Type::Temperature]).is_equal_to(vec![(100, Some(20.1)), (200, Some(20.2))]);
+        assert_that(&series[&Type::Humidity]).is_equal_to(vec![(100, None), (200, Some(44.0))]);
+    }
+
+    #[test]
+    fn limit_is_clamped() {
+        let types = [Type::Temperature];
+        let parameters = Parameters::new("a_station", Scale::Max, &types).limit(5000);
+
+        let params: HashMap<&str, String> = (&parameters).into();
+
+        assert_that(&params.get("limit")).is_equal_to(Some(&"1024".to_string()));
+    }
+
+    #[test]
+    fn history_adaptive_aligns_to_local_windows() {
+        // A station in UTC+1, whose days start at 23:00 UTC
+        let midnight = |day: usize| day * DAY - 3600;
+        let body = |readings: &[usize]| {
+            let values: Vec<_> = readings.iter().map(|x| format!(r#""{}": [1.0]"#, x)).collect();
+            format!(
+                r#"{{"status": "ok", "time_exec": 0.1, "body": {{{}}}}}"#,
+                values.join(", ")
+            )
+        };
+        let client = replay(
+            "history-adaptive",
+            &[
+                (200, body(&[midnight(68), midnight(69), midnight(70)])),
+                (200, body(&[midnight(70), midnight(70) + 3 * 3600, midnight(98)])),
+                (200, body(&[midnight(98) + 900])),
+            ],
+        );
+
+        let readings = history_adaptive(&client, "a_station", "a_module", Type::Rain, 60 * DAY..100 * DAY)
+            .expect("failed to fetch history");
+
+        let timestamps: Vec<_> = readings.iter().map(|x| x.timestamp).collect();
+        assert_that(&timestamps).is_equal_to(vec![
+            midnight(68) + DAY / 2,
+            midnight(69) + DAY / 2,
+            midnight(70) + 3 * 3600 / 2,
+            midnight(70) + 3 * 3600 + 3 * 3600 / 2,
+            midnight(98) + 900,
+        ]);
+    }
+
+    #[test]
+    fn get_measure_all_modules_with_failing_module() {
+        let device: Device = serde_json::from_str(
+            r#"{
+  "_id": "12:34:56:78:90:AB",
+  "co2_calibrating": false,
+  "data_type": ["Temperature", "CO2", "Humidity", "Noise", "Pressure"],
+  "date_setup": 1556295333,
+  "firmware": 140,
+  "last_setup": 1556295333,
+  "last_status_store": 1556451233,
+  "last_upgrade": 1556295520,
+  "modules": [
+    {
+      "_id": "12:34:56:78:90:CD",
+      "battery_percent": 100,
+      "battery_vp": 6190,
+      "data_type": ["Temperature", "Humidity"],
+      "firmware": 46,
+      "last_message": 1556451228,
+      "last_seen": 1556451208,
+      "last_setup": 1556295333,
+      "module_name": "Outside",
+      "reachable": true,
+      "rf_status": 86,
+      "type": "NAModule1"
+    },
+    {
+      "_id": "12:34:56:78:90:EF",
+      "battery_percent": 100,
+      "battery_vp": 6190,
+      "data_type": ["WindStrength", "WindAngle", "GustStrength", "GustAngle"],
+      "firmware": 20,
+      "last_message": 1556451228,
+      "last_seen": 1556451208,
+      "last_setup": 1556295333,
+      "module_name": "Wind",
+      "reachable": true,
+      "rf_status": 86,
+      "type": "NAModule2"
+    }
+  ],
+  "place": {
+    "altitude": 50,
+    "city": "Alert",
+    "country": "CAN",
+    "location": [82.5057837, -62.5575262],
+    "timezone": "EDT"
+  },
+  "reachable": true,
+  "station_name": "Home",
+  "type": "NAMain",
+  "wifi_status": 50
+}"#,
+        )
+        .expect("failed to parse device");
+        let client = replay(
+            "all-modules",
+            &[
+                (
+                    200,
+                    r#"{"status": "ok", "time_exec": 0.1, "body": {"1556451224": [20.3]}}"#.to_string(),
+                ),
+                (
+                    400,
+                    r#"{"error": {"code": 9, "message": "Device not found"}}"#.to_string(),
+                ),
+            ],
+        );
+        let types = [Type::Temperature];
+
+        let measures = get_measure_all_modules(&client, &device, &Parameters::new("", Scale::Max, &types));
+
+        let succeeded: Vec<_> = measures.succeeded().map(|(id, _)| id).collect();
+        let failed: Vec<_> = measures.failed().map(|(id, e)| (id, e.kind().clone())).collect();
+        assert_that(&succeeded).is_equal_to(vec!["12:34:56:78:90:AB"]);
+        assert_that(&failed).is_equal_to(vec![(
+            "12:34:56:78:90:CD",
+            ErrorKind::DeviceNotFound { name: "get_measure" },
+        )]);
+        assert_that(&measures.is_complete()).is_false();
+    }
+
+    #[test]
+    fn check_data_available() {
+        let types = [Type::Temperature];
+        let before_setup = Parameters::new("a_station", Scale::Max, &types)
+            .date_begin(100)
+            .date_end(200);
+        let overlapping = Parameters::new("a_station", Scale::Max, &types)
+            .date_begin(100)
+            .date_end(400);
+
+        assert_that(&before_setup.check_data_available(300).map_err(|e| e.kind().clone())).is_err_containing(
+            ErrorKind::DataNotRetained {
+                date_end: 200,
+                available_from: 300,
+            },
+        );
+        assert_that(&overlapping.check_data_available(300)).is_ok();
+    }
+
+    #[test]
+    fn covered_range() {
+        let measure = measure(
+            &[Type::Temperature],
+            r#"{"status": "ok", "time_exec": 0.1, "body": {"300": [20.1], "100": [20.2], "200": [20.3]}}"#,
+        );
+        let empty = self::measure(
+            &[Type::Temperature],
+            r#"{"status": "ok", "time_exec": 0.1, "body": {}}"#,
+        );
+
+        assert_that(&measure.covered_range()).is_equal_to(Some((100, 300)));
+        assert_that(&empty.covered_range()).is_none();
+    }
+
+    #[test]
+    fn new_readings_skips_seen_timestamps() {
+        let first = measure(
+            &[Type::Temperature],
+            r#"{"status": "ok", "time_exec": 0.1, "body": {"100": [20.1], "200": [20.2]}}"#,
+        );
+        let second = measure(
+            &[Type::Temperature],
+            r#"{"status": "ok", "time_exec": 0.1, "body": {"200": [20.2], "300": [20.3]}}"#,
+        );
+        let mut last_timestamp = None;
+
+        let first = new_readings(first, &mut last_timestamp);
+        let second = new_readings(second, &mut last_timestamp);
+
+        let timestamps = |readings: Vec<Reading>| readings.iter().map(|r| r.timestamp).collect::<Vec<_>>();
+        assert_that(&timestamps(first)).is_equal_to(vec![100, 200]);
+        assert_that(&timestamps(second)).is_equal_to(vec![300]);
+        assert_that(&last_timestamp).is_equal_to(Some(300));
+    }
+
+    #[test]
+    fn sanitize() {
+        let measure = measure(
+            &[Type::Temperature, Type::Humidity],
+            r#"{"status": "ok", "time_exec": 0.1, "body": {"100": [-273, 43], "200": [20.1, null]}}"#,
+        );
+
+        let readings = measure.sanitize().into_readings();
+
+        assert_that(&readings).is_equal_to(vec![
+            Reading {
+                timestamp: 100,
+                values: vec![None, Some(43.0)],
+            },
+            Reading {
+                timestamp: 200,
+                values: vec![Some(20.1), None],
+            },
+        ]);
+    }
+
+    #[test]
+    fn resample() {
+        let measure = measure(
+            &[Type::Temperature, Type::Humidity],
+            r#"{"status": "ok", "time_exec": 0.1, "body": {"1200": [20, 40], "1500": [21, null], "2800": [22, 42]}}"#,
+        );
+        let reading = |timestamp, values| Reading { timestamp, values };
+
+        let mean = measure.resample(Duration::from_secs(600), Resample::Mean);
+        let last = measure.resample(Duration::from_secs(600), Resample::Last);
+        let nearest = measure.resample(Duration::from_secs(600), Resample::Nearest);
+
+        assert_that(&mean).is_equal_to(vec![
+            reading(1200, vec![Some(20.5), Some(40.0)]),
+            reading(1800, vec![None, None]),
+            reading(2400, vec![Some(22.0), Some(42.0)]),
+        ]);
+        assert_that(&last).is_equal_to(vec![
+            reading(1200, vec![Some(21.0), Some(40.0)]),
+            reading(1800, vec![None, None]),
+            reading(2400, vec![Some(22.0), Some(42.0)]),
+        ]);
+        assert_that(&nearest).is_equal_to(vec![
+            reading(1200, vec![Some(20.0), Some(40.0)]),
+            reading(1800, vec![Some(21.0), None]),
+            reading(2400, vec![None, None]),
+            reading(3000, vec![Some(22.0), Some(42.0)]),
+        ]);
+    }
+
+    #[test]
+    fn resample_auto_sums_cumulative_types() {
+        let measure = measure(
+            &[Type::Temperature, Type::Rain],
+            r#"{"status": "ok", "time_exec": 0.1, "body": {"0": [20, 0.5], "300": [21, 1.5]}}"#,
+        );
+
+        let readings = measure.resample(Duration::from_secs(600), Resample::Auto);
+
+        assert_that(&readings).is_equal_to(vec![Reading {
+            timestamp: 0,
+            values: vec![Some(20.5), Some(2.0)],
+        }]);
+    }
+
+    #[test]
+    fn extend_rejects_different_types() {
+        let mut first = measure(
+            &[Type::Temperature],
+            r#"{"status": "ok", "time_exec": 0.1, "body": {}}"#,
+        );
+        let second = measure(&[Type::Humidity], r#"{"status": "ok", "time_exec": 0.1, "body": {}}"#);
+
+        let res = first.extend(second);
+
+        assert_that(&res.map_err(|e| e.kind().clone())).is_err_containing(ErrorKind::MeasureTypesMismatch);
+    }
+}
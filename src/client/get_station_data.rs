@@ -1,24 +1,155 @@
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 use serde::{Deserialize, Serialize};
 
-use crate::{client::AuthenticatedClient, errors::Result};
+use crate::{
+    client::{
+        de::vec_f64_from_str_or_num,
+        module_type::{Battery, ModuleType},
+        AuthenticatedClient,
+    },
+    errors::{ErrorKind, Result},
+};
 
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct StationData {
     pub body: Body,
+    /// "ok" for successful calls
     pub status: String,
+    /// Seconds the server took to process the call
     pub time_exec: f64,
+    /// The server's time of the call in seconds since the epoch
     pub time_server: u64,
 }
 
+impl StationData {
+    /// The JSON schema of the responses of `get_station_data` and `get_homecoachs_data` as expected by this crate, e.g.
+    /// to generate types for other languages from
+    #[cfg(feature = "schema")]
+    pub fn json_schema() -> schemars::schema::RootSchema {
+        schemars::schema_for!(StationData)
+    }
+
+    /// The timestamp of the newest dashboard reading of all devices and their modules
+    pub fn newest_reading(&self) -> Option<u64> {
+        self.body
+            .devices
+            .iter()
+            .flat_map(|d| {
+                d.dashboard_data
+                    .iter()
+                    .chain(d.modules.iter().flat_map(|m| m.dashboard_data.iter()))
+            })
+            .filter_map(|x| x.time_utc)
+            .max()
+    }
+
+    /// Fails if the newest reading is older than `max_age` compared to the local clock
+    pub fn assert_fresh(&self, max_age: Duration) -> Result<()> {
+        self.assert_fresh_at(max_age, unix_now())
+    }
+
+    /// Fails if the newest reading is older than `max_age` compared to the server time of the response
+    ///
+    /// This is not affected by skew between the local clock and Netatmo's clock.
+    pub fn assert_fresh_at_server_time(&self, max_age: Duration) -> Result<()> {
+        self.assert_fresh_at(max_age, self.time_server)
+    }
+
+    /// The dashboard values of all devices and modules that differ from `previous`, e.g. to only notify on changes
+    ///
+    /// Devices and modules without dashboard, or missing from one of the two, count as having no values. Timestamps
+    /// like `time_utc` are not compared as they change with every reading.
+    pub fn diff(&self, previous: &StationData) -> Vec<FieldChange> {
+        let current = self.dashboards();
+        let previous = previous.dashboards();
+        let none = DashboardData::default();
+
+        let mut changes = Vec::new();
+        let mut compare = |module_id: &str, new: &DashboardData, old: &DashboardData| {
+            for ((metric, new), (_, old)) in new.metrics().iter().zip(old.metrics().iter()) {
+                if new != old {
+                    changes.push(FieldChange {
+                        module_id: module_id.to_string(),
+                        metric,
+                        old: *old,
+                        new: *new,
+                    });
+                }
+            }
+        };
+        for (module_id, new) in &current {
+            let old = previous.iter().find(|(id, _)| id == module_id).and_then(|(_, x)| *x);
+            compare(module_id, new.unwrap_or(&none), old.unwrap_or(&none));
+        }
+        for (module_id, old) in &previous {
+            if !current.iter().any(|(id, _)| id == module_id) {
+                compare(module_id, &none, old.unwrap_or(&none));
+            }
+        }
+
+        changes
+    }
+
+    /// The ids and dashboards of all devices and their modules
+    fn dashboards(&self) -> Vec<(&str, Option<&DashboardData>)> {
+        self.body
+            .devices
+            .iter()
+            .flat_map(|d| {
+                std::iter::once((d.id.as_str(), d.dashboard_data.as_ref()))
+                    .chain(d.modules.iter().map(|m| (m.id.as_str(), m.dashboard_data.as_ref())))
+            })
+            .collect()
+    }
+
+    fn assert_fresh_at(&self, max_age: Duration, now: u64) -> Result<()> {
+        let newest = self.newest_reading().ok_or(ErrorKind::NoDashboardData)?;
+        let age = now.saturating_sub(newest);
+        if age > max_age.as_secs() {
+            return Err(ErrorKind::StaleDashboardData {
+                age,
+                max_age: max_age.as_secs(),
+            }
+            .into());
+        }
+
+        Ok(())
+    }
+
+    /// The devices by the mail, or the id if there is no mail, of the user owning them, e.g. to attribute the data of
+    /// favorite stations to their owners
+    ///
+    /// Devices without owner, which are the ones of the authenticated user, are grouped under the mail of
+    /// `body.user`.
+    pub fn group_by_owner(&self) -> HashMap<String, Vec<&Device>> {
+        let mut groups: HashMap<String, Vec<&Device>> = HashMap::new();
+        for device in &self.body.devices {
+            let owner = device
+                .user
+                .as_ref()
+                .and_then(|x| x.mail.as_ref().or(x.id.as_ref()))
+                .unwrap_or(&self.body.user.mail);
+            groups.entry(owner.clone()).or_default().push(device);
+        }
+
+        groups
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Body {
     pub devices: Vec<Device>,
     pub user: User,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Device {
     #[serde(rename = "_id")]
     pub id: String,
@@ -40,9 +171,18 @@ pub struct Device {
     #[serde(default)]
     pub modules: Vec<Module>,
     pub place: Place,
+    /// Whether this is a favorite station of the authenticated user rather than one of their own
+    #[serde(default)]
+    pub favorite: bool,
+    #[serde(default)]
+    pub read_only: bool,
+    /// The user owning the station; usually only reported for favorite stations
+    #[serde(default)]
+    pub user: Option<Owner>,
 }
 
 #[derive(Debug, Default, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct DashboardData {
     #[serde(rename = "AbsolutePressure")]
     pub absolute_pressure: Option<f64>,
@@ -61,16 +201,81 @@ pub struct DashboardData {
     pub date_min_temp: Option<u64>,
     pub max_temp: Option<f64>,
     pub min_temp: Option<f64>,
-    pub pressure_trend: Option<String>,
+    pub pressure_trend: Option<Trend>,
     #[serde(rename = "Rain")]
     pub rain: Option<f64>,
     pub sum_rain_1: Option<f64>,
     pub sum_rain_24: Option<f64>,
-    pub temp_trend: Option<String>,
+    pub temp_trend: Option<Trend>,
     pub time_utc: Option<u64>,
 }
 
+/// The tendency of a measurement as displayed by an arrow in Netatmo's app
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+#[serde(rename_all = "lowercase")]
+pub enum Trend {
+    Up,
+    Down,
+    Stable,
+    /// Any trend unknown to this crate
+    #[serde(other)]
+    Unknown,
+}
+
+/// A dashboard value that differs between two `StationData`, cf. `StationData::diff`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldChange {
+    /// The id of the device or module
+    pub module_id: String,
+    /// The name of the value as used by Netatmo, e.g. "Temperature" or "sum_rain_24"
+    pub metric: &'static str,
+    pub old: Option<f64>,
+    pub new: Option<f64>,
+}
+
+impl FieldChange {
+    /// `new - old`; `None` if either has no value
+    pub fn delta(&self) -> Option<f64> {
+        match (self.old, self.new) {
+            (Some(old), Some(new)) => Some(new - old),
+            _ => None,
+        }
+    }
+}
+
+impl DashboardData {
+    /// The age of this reading compared to the local clock; `None` if the reading has no timestamp
+    pub fn age(&self) -> Option<Duration> {
+        self.age_at(unix_now())
+    }
+
+    /// The age of this reading compared to `now` in seconds since the epoch, e.g. `StationData::time_server`
+    pub fn age_at(&self, now: u64) -> Option<Duration> {
+        self.time_utc.map(|t| Duration::from_secs(now.saturating_sub(t)))
+    }
+
+    /// The numerical values compared by `StationData::diff`
+    fn metrics(&self) -> [(&'static str, Option<f64>); 12] {
+        [
+            ("AbsolutePressure", self.absolute_pressure),
+            ("CO2", self.co2.map(|x| x as f64)),
+            ("Humidity", self.humidity.map(|x| x as f64)),
+            ("Noise", self.noise.map(|x| x as f64)),
+            ("Pressure", self.pressure),
+            ("Temperature", self.temperature),
+            ("health_idx", self.health_idx.map(f64::from)),
+            ("max_temp", self.max_temp),
+            ("min_temp", self.min_temp),
+            ("Rain", self.rain),
+            ("sum_rain_1", self.sum_rain_1),
+            ("sum_rain_24", self.sum_rain_24),
+        ]
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Module {
     #[serde(rename = "_id")]
     pub id: String,
@@ -90,22 +295,56 @@ pub struct Module {
     pub type_info: String,
 }
 
+impl Module {
+    /// The battery level computed from `battery_vp` by the thresholds of the module's type
+    pub fn battery(&self) -> Option<Battery> {
+        let level = ModuleType::from(self.type_info.as_str()).battery_level(self.battery_vp)?;
+        Some(Battery {
+            percent: Some(self.battery_percent),
+            level,
+        })
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Place {
     pub altitude: u64,
     pub city: String,
     pub country: String,
+    /// Longitude and latitude
+    #[serde(deserialize_with = "vec_f64_from_str_or_num")]
     pub location: Vec<f64>,
     pub timezone: String,
 }
 
+impl Place {
+    /// The time zone of the station, cf. `get_measure::Measure::aggregate`; `None` if Netatmo reports an unknown zone
+    #[cfg(feature = "chrono-tz")]
+    pub fn tz(&self) -> Option<chrono_tz::Tz> {
+        self.timezone.parse().ok()
+    }
+}
+
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct User {
     pub administrative: Administrative,
     pub mail: String,
 }
 
+/// The owner of a device as far as Netatmo discloses it
 #[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
+pub struct Owner {
+    #[serde(rename = "_id", default)]
+    pub id: Option<String>,
+    #[serde(default)]
+    pub mail: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+#[cfg_attr(feature = "schema", derive(schemars::JsonSchema))]
 pub struct Administrative {
     pub country: Option<String>,
     pub feel_like_algo: u64,
@@ -116,26 +355,29 @@ pub struct Administrative {
     pub windunit: u64,
 }
 
-pub(crate) fn get_station_data(client: &AuthenticatedClient, device_id: &str) -> Result<StationData> {
+pub(crate) fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_secs())
+        .unwrap_or_default()
+}
+
+pub(crate) fn get_station_data(client: &AuthenticatedClient, device_id: Option<&str>) -> Result<StationData> {
     let mut params: HashMap<&str, &str> = HashMap::default();
-    params.insert("device_id", device_id);
+    if let Some(device_id) = device_id {
+        params.insert("device_id", device_id);
+    }
 
-    client.call(
-        "get_station_data",
-        "https://api.netatmo.com/api/getstationsdata",
-        &mut params,
-    )
+    client.call("get_station_data", "/api/getstationsdata", &params)
 }
 
-pub(crate) fn get_homecoachs_data(client: &AuthenticatedClient, device_id: &str) -> Result<StationData> {
+pub(crate) fn get_homecoachs_data(client: &AuthenticatedClient, device_id: Option<&str>) -> Result<StationData> {
     let mut params: HashMap<&str, &str> = HashMap::default();
-    params.insert("device_id", device_id);
+    if let Some(device_id) = device_id {
+        params.insert("device_id", device_id);
+    }
 
-    client.call(
-        "get_homecoachs_data",
-        "https://api.netatmo.com/api/gethomecoachsdata",
-        &mut params,
-    )
+    client.call("get_homecoachs_data", "/api/gethomecoachsdata", &params)
 }
 
 #[cfg(test)]
@@ -147,9 +389,7 @@ mod test {
     mod get_station_data {
         use super::*;
 
-        #[test]
-        fn parse_response() {
-            let json = r#"{
+        pub(super) const STATION_DATA: &str = r#"{
   "body": {
     "devices": [
       {
@@ -246,10 +486,154 @@ mod test {
   "time_server": 1556451492
 }"#;
 
-            let station_data: ::std::result::Result<StationData, _> = serde_json::from_str(&json);
+        #[test]
+        fn parse_response() {
+            let station_data: ::std::result::Result<StationData, _> = serde_json::from_str(STATION_DATA);
 
             assert_that(&station_data).is_ok();
         }
+
+        #[test]
+        fn parse_location_as_strings() {
+            let json = STATION_DATA.replace("82.5057837,", r#""82.5057837","#);
+
+            let station_data: StationData = serde_json::from_str(&json).expect("failed to parse station data");
+
+            assert_that(&station_data.body.devices[0].place.location).is_equal_to(vec![82.5057837, -62.5575262]);
+        }
+
+        #[test]
+        fn parse_trends() {
+            let station_data: StationData = serde_json::from_str(STATION_DATA).expect("failed to parse station data");
+            let device = &station_data.body.devices[0];
+            let dashboard = device.dashboard_data.as_ref().unwrap();
+            let outdoor = device.modules[0].dashboard_data.as_ref().unwrap();
+
+            assert_that(&dashboard.pressure_trend).is_equal_to(Some(Trend::Up));
+            assert_that(&dashboard.temp_trend).is_equal_to(Some(Trend::Stable));
+            assert_that(&outdoor.pressure_trend).is_none();
+            assert_that(&outdoor.temp_trend).is_equal_to(Some(Trend::Up));
+        }
+
+        #[cfg(feature = "chrono-tz")]
+        #[test]
+        fn tz() {
+            let json = STATION_DATA.replace(r#""timezone": "EDT""#, r#""timezone": "Europe/Berlin""#);
+
+            let station_data: StationData = serde_json::from_str(&json).expect("failed to parse station data");
+
+            assert_that(&station_data.body.devices[0].place.tz()).is_equal_to(Some(chrono_tz::Europe::Berlin));
+        }
+
+        #[test]
+        fn battery() {
+            let station_data: StationData = serde_json::from_str(STATION_DATA).expect("failed to parse station data");
+
+            let battery = station_data.body.devices[0].modules[0].battery();
+
+            assert_that(&battery).is_equal_to(Some(Battery {
+                percent: Some(100),
+                level: crate::client::module_type::BatteryLevel::Full,
+            }));
+        }
+
+        #[test]
+        fn group_by_owner() {
+            let mut station_data: StationData =
+                serde_json::from_str(STATION_DATA).expect("failed to parse station data");
+            let favorite = |id: &str, owner: &str| {
+                let json = STATION_DATA.replace(
+                    r#""wifi_status": 50"#,
+                    &format!(
+                        r#""wifi_status": 50, "favorite": true, "read_only": true, "user": {}"#,
+                        owner
+                    ),
+                );
+                let mut x: StationData = serde_json::from_str(&json).expect("failed to parse station data");
+                let mut device = x.body.devices.remove(0);
+                device.id = id.to_string();
+                device
+            };
+            station_data
+                .body
+                .devices
+                .push(favorite("a", r#"{"mail": "neighbour at domain"}"#));
+            station_data
+                .body
+                .devices
+                .push(favorite("b", r#"{"_id": "5c810xxxxxxx"}"#));
+            station_data
+                .body
+                .devices
+                .push(favorite("c", r#"{"mail": "neighbour at domain"}"#));
+
+            let groups = station_data.group_by_owner();
+            let ids = |owner: &str| groups[owner].iter().map(|x| x.id.as_str()).collect::<Vec<_>>();
+
+            assert_that(&groups.len()).is_equal_to(3);
+            assert_that(&ids("lukas at my_domain")).is_equal_to(vec!["12:34:56:78:90:AB"]);
+            assert_that(&ids("neighbour at domain")).is_equal_to(vec!["a", "c"]);
+            assert_that(&ids("5c810xxxxxxx")).is_equal_to(vec!["b"]);
+            assert_that(&groups["neighbour at domain"][0].favorite).is_true();
+        }
+
+        #[test]
+        fn assert_fresh_at_server_time() {
+            let station_data: StationData = serde_json::from_str(STATION_DATA).expect("failed to parse station data");
+
+            // The newest reading is the station's at 1556451224, the server time is 1556451492
+            assert_that(&station_data.newest_reading()).is_equal_to(Some(1_556_451_224));
+            assert_that(&station_data.assert_fresh_at_server_time(Duration::from_secs(300))).is_ok();
+            assert_that(
+                &station_data
+                    .assert_fresh_at_server_time(Duration::from_secs(60))
+                    .map_err(|e| e.kind().clone()),
+            )
+            .is_err_containing(ErrorKind::StaleDashboardData { age: 268, max_age: 60 });
+        }
+    }
+
+    #[cfg(feature = "schema")]
+    #[test]
+    fn json_schema() {
+        let schema = serde_json::to_value(StationData::json_schema()).expect("failed to serialize schema");
+
+        assert_that(&schema["title"]).is_equal_to(serde_json::json!("StationData"));
+        assert_that(&schema["definitions"]["DashboardData"]["properties"]["Temperature"].is_object()).is_true();
+    }
+
+    mod diff {
+        use super::*;
+
+        #[test]
+        fn reports_changed_values() {
+            let previous: StationData = serde_json::from_str(get_station_data::STATION_DATA).unwrap();
+            let mut current: StationData = serde_json::from_str(get_station_data::STATION_DATA).unwrap();
+            let device = &mut current.body.devices[0];
+            let dashboard = device.dashboard_data.as_mut().unwrap();
+            dashboard.temperature = Some(21.0);
+            dashboard.time_utc = Some(1_556_451_524);
+            device.modules[0].dashboard_data = None;
+
+            let changes = current.diff(&previous);
+            let outdoor = &previous.body.devices[0].modules[0].id;
+
+            assert_that(&changes).contains(FieldChange {
+                module_id: "12:34:56:78:90:AB".to_string(),
+                metric: "Temperature",
+                old: Some(20.3),
+                new: Some(21.0),
+            });
+            assert_that(&changes.iter().filter(|x| &x.module_id != outdoor).count()).is_equal_to(1);
+            assert_that(
+                &changes
+                    .iter()
+                    .filter(|x| &x.module_id == outdoor)
+                    .all(|x| x.new.is_none()),
+            )
+            .is_true();
+            assert_that(&previous.diff(&previous)).is_empty();
+        }
     }
 
     mod get_homecoach_data {
@@ -0,0 +1,227 @@
+use crate::{
+    client::{get_homes_data, get_station_data, module_type::ModuleType, AuthenticatedClient},
+    errors::{ErrorKind, Result},
+};
+
+use log::debug;
+
+/// Netatmo's API error code for a token lacking the scope required by an endpoint
+const SCOPE_MISSING: isize = 13;
+
+/// A device or module accessible with the client's token
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct DeviceRef {
+    pub device_id: String,
+    pub name: Option<String>,
+    pub module_type: ModuleType,
+    /// The home of energy devices; weather stations and home coaches don't belong to a home
+    pub home_id: Option<String>,
+    /// The device this module is attached to, e.g. the weather station of an outdoor module
+    pub bridge: Option<String>,
+}
+
+pub(crate) fn list_devices(client: &AuthenticatedClient) -> Result<Vec<DeviceRef>> {
+    let mut devices = Vec::new();
+
+    if let Some(station_data) = skip_missing_scope(get_station_data::get_station_data(client, None))? {
+        devices.extend(station_devices(station_data));
+    }
+    if let Some(homecoachs_data) = skip_missing_scope(get_station_data::get_homecoachs_data(client, None))? {
+        devices.extend(station_devices(homecoachs_data));
+    }
+    if let Some(homes_data) = skip_missing_scope(get_homes_data::get_homes_data(
+        client,
+        &get_homes_data::Parameters::new(),
+    ))? {
+        devices.extend(homes_data.body.homes.into_iter().flat_map(|home| {
+            let home_id = home.id;
+            home.modules.into_iter().map(move |m| DeviceRef {
+                device_id: m.id,
+                name: Some(m.name),
+                module_type: m.type_field.into(),
+                home_id: Some(home_id.clone()),
+                bridge: m.bridge,
+            })
+        }));
+    }
+
+    Ok(devices)
+}
+
+fn station_devices(station_data: get_station_data::StationData) -> Vec<DeviceRef> {
+    let mut devices = Vec::new();
+    for device in station_data.body.devices {
+        for module in device.modules {
+            devices.push(DeviceRef {
+                device_id: module.id,
+                name: Some(module.module_name),
+                module_type: module.type_info.into(),
+                home_id: None,
+                bridge: Some(device.id.clone()),
+            });
+        }
+        devices.push(DeviceRef {
+            device_id: device.id,
+            name: device.module_name.or(Some(device.station_name)),
+            module_type: device.type_info.into(),
+            home_id: None,
+            bridge: None,
+        });
+    }
+
+    devices
+}
+
+/// Turns an error caused by a missing scope into `None`, so discovery is limited to the granted scopes
+fn skip_missing_scope<T>(res: Result<T>) -> Result<Option<T>> {
+    match res {
+        Ok(x) => Ok(Some(x)),
+        Err(e) => match e.kind() {
+            ErrorKind::ApiCallFailed { name, code, .. } if *code == SCOPE_MISSING => {
+                debug!("Skipping '{}' for device discovery because of missing scope", name);
+                Ok(None)
+            }
+            _ => Err(e),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+    use crate::client::transport::{replay_transcript, TranscriptEntry};
+
+    const STATION_DATA: &str = r#"{
+  "body": {
+    "devices": [
+      {
+        "_id": "12:34:56:78:90:AB",
+        "co2_calibrating": false,
+        "data_type": ["Temperature", "CO2", "Humidity", "Noise", "Pressure"],
+        "date_setup": 1556295333,
+        "firmware": 140,
+        "last_setup": 1556295333,
+        "last_status_store": 1556451233,
+        "last_upgrade": 1556295520,
+        "modules": [
+          {
+            "_id": "12:34:56:78:90:CD",
+            "battery_percent": 100,
+            "battery_vp": 6190,
+            "data_type": ["Temperature", "Humidity"],
+            "firmware": 46,
+            "last_message": 1556451228,
+            "last_seen": 1556451208,
+            "last_setup": 1556295333,
+            "module_name": "Outside",
+            "reachable": true,
+            "rf_status": 86,
+            "type": "NAModule1"
+          }
+        ],
+        "place": {
+          "altitude": 50,
+          "city": "Alert",
+          "country": "CAN",
+          "location": [82.5057837, -62.5575262],
+          "timezone": "EDT"
+        },
+        "reachable": true,
+        "station_name": "Home",
+        "type": "NAMain",
+        "wifi_status": 50
+      }
+    ],
+    "user": {
+      "administrative": {
+        "feel_like_algo": 0,
+        "lang": "en-US",
+        "pressureunit": 0,
+        "reg_locale": "en-US",
+        "unit": 0,
+        "windunit": 0
+      },
+      "mail": "lukas at my_domain"
+    }
+  },
+  "status": "ok",
+  "time_exec": 0.13046002388,
+  "time_server": 1556451492
+}"#;
+
+    const SCOPE_MISSING_BODY: &str = r#"{"error": {"code": 13, "message": "Operation forbidden"}}"#;
+
+    fn replay(name: &str, responses: &[(&str, u16, &str)]) -> AuthenticatedClient {
+        let entries: Vec<_> = responses
+            .iter()
+            .map(|(endpoint, status, body)| TranscriptEntry::response(endpoint, *status, body))
+            .collect();
+        replay_transcript(name, &entries)
+    }
+
+    fn station_refs() -> Vec<DeviceRef> {
+        vec![
+            DeviceRef {
+                device_id: "12:34:56:78:90:CD".to_string(),
+                name: Some("Outside".to_string()),
+                module_type: ModuleType::OutdoorModule,
+                home_id: None,
+                bridge: Some("12:34:56:78:90:AB".to_string()),
+            },
+            DeviceRef {
+                device_id: "12:34:56:78:90:AB".to_string(),
+                name: Some("Home".to_string()),
+                module_type: ModuleType::WeatherStation,
+                home_id: None,
+                bridge: None,
+            },
+        ]
+    }
+
+    #[test]
+    fn station_devices_with_modules() {
+        let station_data = serde_json::from_str(STATION_DATA).expect("failed to parse station data");
+
+        let devices = station_devices(station_data);
+
+        assert_that(&devices).is_equal_to(station_refs());
+    }
+
+    #[test]
+    fn list_devices_skips_missing_scopes() {
+        let client = replay(
+            "list-devices-scopes",
+            &[
+                ("get_station_data", 200, STATION_DATA),
+                ("get_homecoachs_data", 403, SCOPE_MISSING_BODY),
+                ("get_homes_data", 403, SCOPE_MISSING_BODY),
+            ],
+        );
+
+        let devices = list_devices(&client);
+
+        assert_that(&devices).is_ok_containing(station_refs());
+    }
+
+    #[test]
+    fn list_devices_fails_on_other_errors() {
+        let client = replay(
+            "list-devices-errors",
+            &[(
+                "get_station_data",
+                403,
+                r#"{"error": {"code": 2, "message": "Invalid access token"}}"#,
+            )],
+        );
+
+        let res = list_devices(&client).map_err(|e| e.kind().clone());
+
+        assert_that(&res).is_err_containing(ErrorKind::ApiCallFailed {
+            name: "get_station_data",
+            code: 2,
+            msg: "Invalid access token".to_string(),
+        });
+    }
+}
@@ -0,0 +1,263 @@
+use crate::client::get_measure::Type;
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// The hardware type of a Netatmo device or module as reported in the `type` field of the API responses
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum ModuleType {
+    /// NAMain
+    WeatherStation,
+    /// NAModule1
+    OutdoorModule,
+    /// NAModule2
+    WindGauge,
+    /// NAModule3
+    RainGauge,
+    /// NAModule4
+    IndoorModule,
+    /// NHC
+    HomeCoach,
+    /// NAPlug
+    Relay,
+    /// NATherm1
+    Thermostat,
+    /// NRV
+    Valve,
+    /// OTH
+    OpenThermRelay,
+    /// OTM
+    OpenThermModulator,
+    /// NACamera
+    IndoorCamera,
+    /// NOC
+    OutdoorCamera,
+    /// NSD
+    SmokeDetector,
+    /// NCO
+    CoDetector,
+    /// NDB
+    Doorbell,
+    /// NIS
+    Siren,
+    /// NACamDoorTag
+    DoorTag,
+    /// NLG
+    LegrandGateway,
+    /// NLP
+    LegrandPlug,
+    /// NLF
+    LegrandDimmer,
+    /// NXG
+    VeluxGateway,
+    /// NXO, a Velux window or shutter
+    VeluxOpening,
+    /// NXS
+    VeluxSensor,
+    /// NXD, a Velux departure switch
+    VeluxSwitch,
+    /// NBR, a Bubendorff roller shutter
+    RollerShutter,
+    /// Any type unknown to this crate
+    Other(String),
+}
+
+impl ModuleType {
+    /// The measurement types this module type supports in `get_measure`; empty for types without weather measurements
+    pub fn measurement_capabilities(&self) -> &'static [Type] {
+        use ModuleType::*;
+        match self {
+            WeatherStation | HomeCoach => &[
+                Type::Temperature,
+                Type::CO2,
+                Type::Humidity,
+                Type::Pressure,
+                Type::Noise,
+            ],
+            OutdoorModule => &[Type::Temperature, Type::Humidity],
+            WindGauge => &[Type::WindStrength, Type::WindAngle, Type::GustStrength, Type::GustAngle],
+            RainGauge => &[Type::Rain, Type::SumRain],
+            IndoorModule => &[Type::Temperature, Type::CO2, Type::Humidity],
+            Thermostat => &[
+                Type::Temperature,
+                Type::BoilerOn,
+                Type::BoilerOff,
+                Type::SumBoilerOn,
+                Type::SumBoilerOff,
+            ],
+            _ => &[],
+        }
+    }
+
+    /// Whether this module type supports measurements of type `measurement_type`
+    pub fn supports(&self, measurement_type: &Type) -> bool {
+        self.measurement_capabilities().contains(measurement_type)
+    }
+
+    /// The battery level of a module of this type at `voltage` in mV; `None` for types without documented levels
+    pub fn battery_level(&self, voltage: u64) -> Option<BatteryLevel> {
+        let [full, high, medium, low] = self.battery_thresholds()?;
+        let level = match voltage {
+            v if v >= full => BatteryLevel::Full,
+            v if v >= high => BatteryLevel::High,
+            v if v >= medium => BatteryLevel::Medium,
+            v if v >= low => BatteryLevel::Low,
+            _ => BatteryLevel::VeryLow,
+        };
+        Some(level)
+    }
+
+    /// The minimum voltages in mV of the levels `Full`, `High`, `Medium`, and `Low` as documented by Netatmo
+    ///
+    /// Cf. `battery_vp` at https://dev.netatmo.com/apidocumentation/weather#getstationsdata for the weather modules,
+    /// and `battery_level` at https://dev.netatmo.com/apidocumentation/energy#homestatus for thermostats and valves.
+    fn battery_thresholds(&self) -> Option<[u64; 4]> {
+        use ModuleType::*;
+        match self {
+            OutdoorModule | RainGauge => Some([5500, 5000, 4500, 4000]),
+            IndoorModule => Some([5640, 5280, 4920, 4560]),
+            WindGauge => Some([5590, 5180, 4770, 4360]),
+            Thermostat => Some([4100, 3600, 3300, 3000]),
+            Valve => Some([3200, 2700, 2400, 2200]),
+            _ => None,
+        }
+    }
+
+    /// Whether this type belongs to Netatmo's security product line, i.e. produces events
+    pub fn is_security(&self) -> bool {
+        use ModuleType::*;
+        matches!(
+            self,
+            IndoorCamera | OutdoorCamera | SmokeDetector | CoDetector | Doorbell | Siren | DoorTag
+        )
+    }
+}
+
+/// A battery level comparable across module types; levels are ordered from `VeryLow` to `Full`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum BatteryLevel {
+    VeryLow,
+    Low,
+    Medium,
+    High,
+    Full,
+}
+
+impl BatteryLevel {
+    /// The level of a `battery_state` as reported by `homestatus`
+    pub(crate) fn from_state(state: &str) -> Option<Self> {
+        match state {
+            "max" | "full" => Some(BatteryLevel::Full),
+            "high" => Some(BatteryLevel::High),
+            "medium" => Some(BatteryLevel::Medium),
+            "low" => Some(BatteryLevel::Low),
+            "very_low" => Some(BatteryLevel::VeryLow),
+            _ => None,
+        }
+    }
+}
+
+/// The battery of a module, cf. `get_station_data::Module::battery` and `get_home_status::Module::battery`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Battery {
+    /// The charge as reported by Netatmo, if reported
+    pub percent: Option<u64>,
+    pub level: BatteryLevel,
+}
+
+impl fmt::Display for ModuleType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let s = match self {
+            ModuleType::WeatherStation => "NAMain",
+            ModuleType::OutdoorModule => "NAModule1",
+            ModuleType::WindGauge => "NAModule2",
+            ModuleType::RainGauge => "NAModule3",
+            ModuleType::IndoorModule => "NAModule4",
+            ModuleType::HomeCoach => "NHC",
+            ModuleType::Relay => "NAPlug",
+            ModuleType::Thermostat => "NATherm1",
+            ModuleType::Valve => "NRV",
+            ModuleType::OpenThermRelay => "OTH",
+            ModuleType::OpenThermModulator => "OTM",
+            ModuleType::IndoorCamera => "NACamera",
+            ModuleType::OutdoorCamera => "NOC",
+            ModuleType::SmokeDetector => "NSD",
+            ModuleType::CoDetector => "NCO",
+            ModuleType::Doorbell => "NDB",
+            ModuleType::Siren => "NIS",
+            ModuleType::DoorTag => "NACamDoorTag",
+            ModuleType::LegrandGateway => "NLG",
+            ModuleType::LegrandPlug => "NLP",
+            ModuleType::LegrandDimmer => "NLF",
+            ModuleType::VeluxGateway => "NXG",
+            ModuleType::VeluxOpening => "NXO",
+            ModuleType::VeluxSensor => "NXS",
+            ModuleType::VeluxSwitch => "NXD",
+            ModuleType::RollerShutter => "NBR",
+            ModuleType::Other(s) => s,
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl From<&str> for ModuleType {
+    fn from(s: &str) -> Self {
+        match s {
+            "NAMain" => ModuleType::WeatherStation,
+            "NAModule1" => ModuleType::OutdoorModule,
+            "NAModule2" => ModuleType::WindGauge,
+            "NAModule3" => ModuleType::RainGauge,
+            "NAModule4" => ModuleType::IndoorModule,
+            "NHC" => ModuleType::HomeCoach,
+            "NAPlug" => ModuleType::Relay,
+            "NATherm1" => ModuleType::Thermostat,
+            "NRV" => ModuleType::Valve,
+            "OTH" => ModuleType::OpenThermRelay,
+            "OTM" => ModuleType::OpenThermModulator,
+            "NACamera" => ModuleType::IndoorCamera,
+            "NOC" => ModuleType::OutdoorCamera,
+            "NSD" => ModuleType::SmokeDetector,
+            "NCO" => ModuleType::CoDetector,
+            "NDB" => ModuleType::Doorbell,
+            "NIS" => ModuleType::Siren,
+            "NACamDoorTag" => ModuleType::DoorTag,
+            "NLG" => ModuleType::LegrandGateway,
+            "NLP" => ModuleType::LegrandPlug,
+            "NLF" => ModuleType::LegrandDimmer,
+            "NXG" => ModuleType::VeluxGateway,
+            "NXO" => ModuleType::VeluxOpening,
+            "NXS" => ModuleType::VeluxSensor,
+            "NXD" => ModuleType::VeluxSwitch,
+            "NBR" => ModuleType::RollerShutter,
+            other => ModuleType::Other(other.to_string()),
+        }
+    }
+}
+
+impl From<String> for ModuleType {
+    fn from(s: String) -> Self {
+        ModuleType::from(s.as_str())
+    }
+}
+
+impl From<ModuleType> for String {
+    fn from(module_type: ModuleType) -> Self {
+        module_type.to_string()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use spectral::prelude::*;
+
+    #[test]
+    fn battery_level_by_type() {
+        assert_that(&ModuleType::OutdoorModule.battery_level(5500)).is_equal_to(Some(BatteryLevel::Full));
+        assert_that(&ModuleType::RainGauge.battery_level(4200)).is_equal_to(Some(BatteryLevel::Low));
+        assert_that(&ModuleType::IndoorModule.battery_level(5500)).is_equal_to(Some(BatteryLevel::High));
+        assert_that(&ModuleType::WindGauge.battery_level(4300)).is_equal_to(Some(BatteryLevel::VeryLow));
+        assert_that(&ModuleType::Relay.battery_level(5000)).is_none();
+    }
+}
@@ -0,0 +1,61 @@
+use crate::{
+    client::{
+        authenticate::Token,
+        get_events::{self, Events},
+        get_home_status::{self, HomeStatus},
+        get_homes_data::{self, HomesData},
+        get_measure::{self, Measure},
+        get_station_data::StationData,
+        list_devices::DeviceRef,
+        AuthenticatedClient, Netatmo,
+    },
+    errors::Result,
+};
+
+/// An authenticated client that only offers the reading API calls
+///
+/// Code handed a `ReadOnlyClient` cannot change the state of a home, e.g. by `set_room_thermpoint`, which is checked at
+/// compile time. Such a client only needs to be authorized for the `read_*` scopes.
+pub struct ReadOnlyClient {
+    client: AuthenticatedClient,
+}
+
+impl From<AuthenticatedClient> for ReadOnlyClient {
+    fn from(client: AuthenticatedClient) -> Self {
+        ReadOnlyClient { client }
+    }
+}
+
+impl ReadOnlyClient {
+    pub fn token(&self) -> Token {
+        self.client.token()
+    }
+
+    pub fn get_events(&self, parameters: &get_events::Parameters) -> Result<Events> {
+        Netatmo::get_events(&self.client, parameters)
+    }
+
+    pub fn get_home_status(&self, parameters: &get_home_status::Parameters) -> Result<HomeStatus> {
+        Netatmo::get_home_status(&self.client, parameters)
+    }
+
+    pub fn get_homes_data(&self, parameters: &get_homes_data::Parameters) -> Result<HomesData> {
+        get_homes_data::get_homes_data(&self.client, parameters)
+    }
+
+    pub fn get_station_data(&self, device_id: &str) -> Result<StationData> {
+        Netatmo::get_station_data(&self.client, device_id)
+    }
+
+    pub fn get_homecoachs_data(&self, device_id: &str) -> Result<StationData> {
+        Netatmo::get_homecoachs_data(&self.client, device_id)
+    }
+
+    pub fn get_measure(&self, parameters: &get_measure::Parameters) -> Result<Measure> {
+        get_measure::get_measure(&self.client, parameters)
+    }
+
+    pub fn list_devices(&self) -> Result<Vec<DeviceRef>> {
+        self.client.list_devices()
+    }
+}
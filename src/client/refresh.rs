@@ -0,0 +1,249 @@
+use std::{
+    sync::{
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+        Arc, Mutex, RwLock,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use log::{debug, warn};
+
+use crate::client::{
+    authenticate, authenticate::Token, transport::Transport, ClientCredentials, UnauthenticatedClient,
+};
+
+/// The delay before retrying a failed refresh
+const RETRY_DELAY: Duration = Duration::from_secs(60);
+/// How long stopping the refresh tasks waits for them to finish, e.g. a refresh in progress
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(1);
+
+pub(crate) type TokenCallback = Arc<dyn Fn(&Token) + Send + Sync>;
+
+/// The token of a client together with the time it was received, which `expires_in` counts from
+#[derive(Debug)]
+pub(crate) struct SharedToken {
+    pub(crate) token: Token,
+    pub(crate) received: Instant,
+}
+
+impl SharedToken {
+    pub(crate) fn new(token: Token) -> Arc<RwLock<SharedToken>> {
+        Arc::new(RwLock::new(SharedToken {
+            token,
+            received: Instant::now(),
+        }))
+    }
+
+    fn expires_at(&self) -> Instant {
+        self.received + Duration::from_secs(self.token.expires_in)
+    }
+}
+
+/// Client credentials owned by an authenticated client to refresh its token
+#[derive(Debug, Clone)]
+pub(crate) struct OwnedCredentials {
+    pub(crate) client_id: String,
+    pub(crate) client_secret: String,
+}
+
+impl<'a> From<&'a ClientCredentials<'a>> for OwnedCredentials {
+    fn from(credentials: &'a ClientCredentials<'a>) -> Self {
+        OwnedCredentials {
+            client_id: credentials.client_id.to_string(),
+            client_secret: credentials.client_secret.to_string(),
+        }
+    }
+}
+
+/// A running refresh task; dropping it stops the task, too
+#[derive(Debug)]
+pub(crate) struct RefreshTask {
+    shutdown: Sender<()>,
+    /// Disconnects when the thread finishes
+    stopped: Receiver<()>,
+}
+
+/// The refresh tasks of a client, which are stopped when it is dropped
+#[derive(Debug, Default)]
+pub(crate) struct RefreshTasks {
+    tasks: Mutex<Vec<RefreshTask>>,
+}
+
+impl RefreshTasks {
+    pub(crate) fn push(&self, task: RefreshTask) {
+        self.tasks.lock().expect("refresh tasks lock poisoned").push(task);
+    }
+
+    /// Signals all tasks to stop and waits up to `SHUTDOWN_TIMEOUT` for them to finish
+    ///
+    /// Returns whether all tasks finished in time.
+    pub(crate) fn stop(&self) -> bool {
+        let tasks: Vec<_> = self
+            .tasks
+            .lock()
+            .expect("refresh tasks lock poisoned")
+            .drain(..)
+            .collect();
+        for task in &tasks {
+            // Fails if the task already finished, which is what we want anyway
+            let _ = task.shutdown.send(());
+        }
+
+        let deadline = Instant::now() + SHUTDOWN_TIMEOUT;
+        tasks.iter().all(|task| {
+            let timeout = deadline.saturating_duration_since(Instant::now());
+            matches!(task.stopped.recv_timeout(timeout), Err(RecvTimeoutError::Disconnected))
+        })
+    }
+}
+
+impl Drop for RefreshTasks {
+    fn drop(&mut self) {
+        if !self.stop() {
+            warn!("Token refresh task did not stop within {}s", SHUTDOWN_TIMEOUT.as_secs());
+        }
+    }
+}
+
+/// Spawns a thread refreshing `token` `margin` before it expires until the returned task is stopped or dropped
+pub(crate) fn spawn(
+    token: Arc<RwLock<SharedToken>>,
+    credentials: OwnedCredentials,
+    transport: Transport,
+    on_token_refresh: Option<TokenCallback>,
+    margin: Duration,
+) -> (RefreshTask, JoinHandle<()>) {
+    let (shutdown, shutdown_requested) = mpsc::channel();
+    let (stopped_sender, stopped) = mpsc::channel::<()>();
+    let handle = thread::spawn(move || {
+        // Dropped when the thread finishes, which disconnects `stopped`
+        let _stopped_sender = stopped_sender;
+
+        let client_credentials = ClientCredentials {
+            client_id: &credentials.client_id,
+            client_secret: &credentials.client_secret,
+        };
+        let client = UnauthenticatedClient {
+            client_credentials: &client_credentials,
+            scopes: Vec::new(),
+            transport,
+        };
+
+        let mut wait = refresh_delay(&token, margin);
+        loop {
+            match shutdown_requested.recv_timeout(wait) {
+                Err(RecvTimeoutError::Timeout) => {}
+                _ => {
+                    debug!("Stopping token refresh task");
+                    return;
+                }
+            }
+
+            let refresh_token = token.read().expect("token lock poisoned").token.refresh_token.clone();
+            match authenticate::refresh_token(&client, &refresh_token) {
+                Ok(refreshed) => {
+                    debug!("Refreshed token expiring in {}s", refreshed.expires_in);
+                    *token.write().expect("token lock poisoned") = SharedToken {
+                        token: refreshed.clone(),
+                        received: Instant::now(),
+                    };
+                    if let Some(ref on_token_refresh) = on_token_refresh {
+                        on_token_refresh(&refreshed);
+                    }
+                    wait = refresh_delay(&token, margin);
+                }
+                Err(e) => {
+                    warn!("Failed to refresh token, retrying in {}s: {}", RETRY_DELAY.as_secs(), e);
+                    wait = RETRY_DELAY;
+                }
+            }
+        }
+    });
+
+    (RefreshTask { shutdown, stopped }, handle)
+}
+
+fn refresh_delay(token: &RwLock<SharedToken>, margin: Duration) -> Duration {
+    let expires_at = token.read().expect("token lock poisoned").expires_at();
+    expires_at
+        .checked_sub(margin)
+        .map(|x| x.saturating_duration_since(Instant::now()))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    fn token(expires_in: u64) -> Token {
+        Token {
+            access_token: "access|1".to_string(),
+            refresh_token: "refresh|1".to_string(),
+            scope: Vec::new(),
+            expires_in,
+            expire_in: expires_in,
+        }
+    }
+
+    #[test]
+    fn refresh_delay_subtracts_margin() {
+        let token = SharedToken::new(token(10800));
+
+        let delay = refresh_delay(&token, Duration::from_secs(300));
+
+        assert_that(&delay).is_less_than_or_equal_to(Duration::from_secs(10500));
+        assert_that(&delay).is_greater_than(Duration::from_secs(10490));
+    }
+
+    #[test]
+    fn refresh_delay_of_expiring_token() {
+        let token = SharedToken::new(token(60));
+
+        assert_that(&refresh_delay(&token, Duration::from_secs(300))).is_equal_to(Duration::from_secs(0));
+    }
+
+    #[test]
+    fn stops_when_sender_is_dropped() {
+        let token = SharedToken::new(token(10800));
+        let credentials = OwnedCredentials {
+            client_id: "client".to_string(),
+            client_secret: "secret".to_string(),
+        };
+
+        let (task, handle) = spawn(token, credentials, Transport::new(), None, Duration::from_secs(300));
+        drop(task);
+
+        assert_that(&handle.join()).is_ok();
+    }
+
+    #[test]
+    fn stop_waits_for_tasks() {
+        let credentials = OwnedCredentials {
+            client_id: "client".to_string(),
+            client_secret: "secret".to_string(),
+        };
+        let tasks = RefreshTasks::default();
+        let mut handles = Vec::new();
+        for _ in 0..2 {
+            let token = SharedToken::new(token(10800));
+            let (task, handle) = spawn(
+                token,
+                credentials.clone(),
+                Transport::new(),
+                None,
+                Duration::from_secs(300),
+            );
+            tasks.push(task);
+            handles.push(handle);
+        }
+
+        let stopped = tasks.stop();
+
+        assert_that(&stopped).is_true();
+        assert_that(&handles.into_iter().all(|x| x.join().is_ok())).is_true();
+        assert_that(&tasks.stop()).is_true();
+    }
+}
@@ -1,7 +1,15 @@
-use crate::{client::AuthenticatedClient, errors::Result};
+use crate::{
+    client::{get_home_status::HomeStatus, AuthenticatedClient},
+    errors::{ErrorKind, Result},
+};
 
 use serde::Deserialize;
-use std::{collections::HashMap, fmt};
+use std::{collections::HashMap, fmt, ops::RangeInclusive};
+
+pub use crate::get_home_status::HvacMode;
+
+/// The cooling setpoints in °C that air conditioners and heat pumps accept
+pub const COOLING_TEMP_RANGE: RangeInclusive<f32> = 16.0..=30.0;
 
 pub struct Parameters<'a> {
     home_id: &'a str,
@@ -9,6 +17,7 @@ pub struct Parameters<'a> {
     mode: Mode,
     temp: Option<f32>,
     endtime: Option<usize>,
+    hvac_mode: HvacMode,
 }
 
 pub enum Mode {
@@ -33,6 +42,7 @@ impl<'a> Parameters<'a> {
             mode,
             temp: None,
             endtime: None,
+            hvac_mode: HvacMode::Heating,
         }
     }
 
@@ -49,6 +59,71 @@ impl<'a> Parameters<'a> {
             ..self
         }
     }
+
+    /// Sets whether the heating or the cooling setpoint is changed; defaults to heating
+    ///
+    /// Netatmo's reference of `setroomthermpoint` only documents the heating parameters `mode`, `temp` and `endtime`,
+    /// so `set_room_thermpoint` rejects cooling setpoints with `ErrorKind::InvalidParameter` rather than sending
+    /// guessed parameters. Temperatures outside `COOLING_TEMP_RANGE` are reported as such first.
+    pub fn hvac_mode(self, hvac_mode: HvacMode) -> Self {
+        Parameters { hvac_mode, ..self }
+    }
+
+    /// Applies the setpoint to the room in `home_status` as if Netatmo had already taken it over, e.g. to render the
+    /// change immediately after a successful `set_room_thermpoint`
+    ///
+    /// This is optimistic: the thermostat may apply the change later or not at all, and back to `Mode::Home` the
+    /// setpoint of the schedule is unknown and kept as is, so the next `get_home_status` is authoritative. Returns
+    /// `false` if `home_status` has no such home and room, or for cooling setpoints, which cannot be set yet.
+    pub fn apply_to(&self, home_status: &mut HomeStatus) -> bool {
+        let home = &mut home_status.body.home;
+        if home.id != self.home_id {
+            return false;
+        }
+        let room = match home.rooms.iter_mut().find(|x| x.id == self.room_id) {
+            Some(room) => room,
+            None => return false,
+        };
+        let endtime = match self.mode {
+            Mode::Manual => self.endtime.map(|x| x as i64),
+            Mode::Home => Some(0),
+        };
+        if self.hvac_mode != HvacMode::Heating {
+            return false;
+        }
+        room.therm_setpoint_mode = self.mode.to_string();
+        if let Some(temp) = self.temp {
+            room.therm_setpoint_temperature = f64::from(temp);
+        }
+        if let Some(endtime) = endtime {
+            room.therm_setpoint_end_time = endtime;
+        }
+
+        true
+    }
+
+    fn validate(&self) -> Result<()> {
+        let (name, reason) = match self.hvac_mode {
+            HvacMode::Heating => return Ok(()),
+            HvacMode::Cooling => match self.temp {
+                Some(temp) if !COOLING_TEMP_RANGE.contains(&temp) => (
+                    "temp",
+                    format!(
+                        "cooling setpoints range from {} to {} °C",
+                        COOLING_TEMP_RANGE.start(),
+                        COOLING_TEMP_RANGE.end()
+                    ),
+                ),
+                _ => (
+                    "hvac_mode",
+                    "cooling setpoints cannot be set until Netatmo documents their parameters".to_string(),
+                ),
+            },
+            HvacMode::Unknown => ("hvac_mode", "setpoints can only be set for heating".to_string()),
+        };
+
+        Err(ErrorKind::InvalidParameter { name, reason }.into())
+    }
 }
 
 pub enum Type {
@@ -75,11 +150,11 @@ impl<'a> From<&'a Parameters<'a>> for HashMap<&str, String> {
         map.insert("home_id", p.home_id.to_string());
         map.insert("room_id", p.room_id.to_string());
         map.insert("mode", p.mode.to_string());
-        if let Some(temp) = p.temp {
-            map.insert("temp", temp.to_string());
+        if let Some(t) = p.temp {
+            map.insert("temp", t.to_string());
         }
-        if let Some(endtime) = p.endtime {
-            map.insert("endtime", endtime.to_string());
+        if let Some(e) = p.endtime {
+            map.insert("endtime", e.to_string());
         }
 
         map
@@ -94,12 +169,106 @@ pub struct Response {
 
 // cf. https://dev.netatmo.com/resources/technical/reference/energy/setroomthermpoint
 pub fn set_room_thermpoint(client: &AuthenticatedClient, parameters: &Parameters) -> Result<Response> {
+    parameters.validate()?;
     let params: HashMap<&str, String> = parameters.into();
-    let mut params = params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+    let params = params.iter().map(|(k, v)| (*k, v.as_ref())).collect();
+
+    client.call_write("set_room_thermpoint", "/api/setroomthermpoint", &params)
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+    use crate::client::transport::{replay_transcript, TranscriptEntry};
+
+    fn cooling(temp: f32) -> std::result::Result<(), ErrorKind> {
+        Parameters::new("a_home", "a_room", Mode::Manual)
+            .temp(temp)
+            .hvac_mode(HvacMode::Cooling)
+            .validate()
+            .map_err(|e| e.kind().clone())
+    }
+
+    fn cooling_out_of_range() -> ErrorKind {
+        ErrorKind::InvalidParameter {
+            name: "temp",
+            reason: "cooling setpoints range from 16 to 30 °C".to_string(),
+        }
+    }
+
+    fn cooling_unsupported() -> ErrorKind {
+        ErrorKind::InvalidParameter {
+            name: "hvac_mode",
+            reason: "cooling setpoints cannot be set until Netatmo documents their parameters".to_string(),
+        }
+    }
+
+    #[test]
+    fn cooling_below_range() {
+        assert_that(&cooling(15.9)).is_err_containing(cooling_out_of_range());
+        assert_that(&cooling(16.0)).is_err_containing(cooling_unsupported());
+    }
+
+    #[test]
+    fn cooling_above_range() {
+        assert_that(&cooling(30.1)).is_err_containing(cooling_out_of_range());
+        assert_that(&cooling(30.0)).is_err_containing(cooling_unsupported());
+    }
 
-    client.call(
-        "set_room_thermpoint",
-        "https://api.netatmo.com/api/setroomthermpoint",
-        &mut params,
-    )
+    #[test]
+    fn cooling_is_not_sent() {
+        let client = replay_transcript("setpoint-cooling", &[]);
+        let parameters = Parameters::new("a_home", "a_room", Mode::Manual)
+            .temp(24.5)
+            .hvac_mode(HvacMode::Cooling);
+
+        let res = set_room_thermpoint(&client, &parameters);
+
+        assert_that(&res.map(|_| ()).map_err(|e| e.kind().clone())).is_err_containing(cooling_unsupported());
+    }
+
+    #[test]
+    fn apply_to() {
+        let mut home_status = HomeStatus::default();
+        home_status.body.home.id = "a_home".to_string();
+        home_status.body.home.rooms = vec![Default::default()];
+        home_status.body.home.rooms[0].id = "a_room".to_string();
+        home_status.body.home.rooms[0].therm_setpoint_mode = "home".to_string();
+
+        let applied = Parameters::new("a_home", "a_room", Mode::Manual)
+            .temp(21.5)
+            .date_end(1_600_000_000)
+            .apply_to(&mut home_status);
+        let room = &home_status.body.home.rooms[0];
+
+        assert_that(&applied).is_true();
+        assert_that(&room.therm_setpoint_mode.as_str()).is_equal_to("manual");
+        assert_that(&room.therm_setpoint_temperature).is_equal_to(21.5);
+        assert_that(&room.therm_setpoint_end_time).is_equal_to(1_600_000_000);
+        assert_that(&room.cooling_setpoint_mode).is_none();
+        let cooling = Parameters::new("a_home", "a_room", Mode::Home).hvac_mode(HvacMode::Cooling);
+        assert_that(&cooling.apply_to(&mut home_status)).is_false();
+        assert_that(&Parameters::new("a_home", "another_room", Mode::Home).apply_to(&mut home_status)).is_false();
+    }
+
+    #[test]
+    fn server_errors_are_not_retried() {
+        let client = replay_transcript(
+            "setpoint-no-retry",
+            &[
+                TranscriptEntry::response("set_room_thermpoint", 502, "Bad Gateway"),
+                TranscriptEntry::response(
+                    "set_room_thermpoint",
+                    200,
+                    r#"{"status": "ok", "time_server": 1556451492}"#,
+                ),
+            ],
+        );
+
+        let res = set_room_thermpoint(&client, &Parameters::new("a_home", "a_room", Mode::Home));
+
+        assert_that(&res).is_err();
+    }
 }
@@ -0,0 +1,202 @@
+use crate::{
+    client::AuthenticatedClient,
+    errors::{ErrorKind, Result},
+};
+
+use serde::{Deserialize, Serialize};
+
+/// The state changes of one or more modules of a home
+///
+/// Which changes a module accepts depends on its type:
+///
+/// * Legrand plugs (NLP) can be switched on and off by `plug`.
+/// * Legrand dimmers (NLF) can be switched on and off by `plug` and dimmed by `dimmer`.
+/// * Velux windows and shutters (NXO) and Bubendorff roller shutters (NBR) can be moved by `shutter`.
+///
+/// Legrand modules are addressed via their gateway (NLG) and Velux modules via theirs (NXG) which has to be passed as
+/// `bridge`.
+///
+/// Netatmo answers as soon as it has accepted the changes, so a shutter may still be moving when `set_state`
+/// returns; poll `get_home_status` for its `current_position` to follow it.
+pub struct Parameters<'a> {
+    home_id: &'a str,
+    modules: Vec<ModuleState<'a>>,
+}
+
+#[derive(Debug, Serialize)]
+struct ModuleState<'a> {
+    id: &'a str,
+    bridge: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    on: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    brightness: Option<u8>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    target_position: Option<u8>,
+}
+
+impl<'a> Parameters<'a> {
+    pub fn new(home_id: &'a str) -> Self {
+        Parameters {
+            home_id,
+            modules: Vec::new(),
+        }
+    }
+
+    /// Switches a plug or dimmer on or off
+    pub fn plug(mut self, module_id: &'a str, bridge: &'a str, on: bool) -> Self {
+        self.modules.push(ModuleState {
+            id: module_id,
+            bridge,
+            on: Some(on),
+            brightness: None,
+            target_position: None,
+        });
+        self
+    }
+
+    /// Sets the brightness of a dimmer from 0 to 100
+    pub fn dimmer(mut self, module_id: &'a str, bridge: &'a str, brightness: u8) -> Self {
+        self.modules.push(ModuleState {
+            id: module_id,
+            bridge,
+            on: None,
+            brightness: Some(brightness),
+            target_position: None,
+        });
+        self
+    }
+
+    /// Moves a shutter or window to a position from 0 (closed) to 100 (open)
+    pub fn shutter(mut self, module_id: &'a str, bridge: &'a str, target_position: u8) -> Self {
+        self.modules.push(ModuleState {
+            id: module_id,
+            bridge,
+            on: None,
+            brightness: None,
+            target_position: Some(target_position),
+        });
+        self
+    }
+
+    fn validate(&self) -> Result<()> {
+        for module in &self.modules {
+            match module.brightness {
+                Some(brightness) if brightness > 100 => {
+                    return Err(ErrorKind::InvalidParameter {
+                        name: "brightness",
+                        reason: format!("brightness {} of module '{}' exceeds 100", brightness, module.id),
+                    }
+                    .into())
+                }
+                _ => {}
+            }
+            match module.target_position {
+                Some(position) if position > 100 => {
+                    return Err(ErrorKind::InvalidParameter {
+                        name: "target_position",
+                        reason: format!("target position {} of module '{}' exceeds 100", position, module.id),
+                    }
+                    .into())
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct Body<'a> {
+    home: HomeState<'a>,
+}
+
+#[derive(Serialize)]
+struct HomeState<'a> {
+    id: &'a str,
+    modules: &'a [ModuleState<'a>],
+}
+
+impl<'a> From<&'a Parameters<'a>> for Body<'a> {
+    fn from(p: &'a Parameters) -> Body<'a> {
+        Body {
+            home: HomeState {
+                id: p.home_id,
+                modules: &p.modules,
+            },
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Response {
+    pub status: String,
+    pub time_server: usize,
+}
+
+// cf. https://dev.netatmo.com/apidocumentation/control#setstate
+pub fn set_state(client: &AuthenticatedClient, parameters: &Parameters) -> Result<Response> {
+    parameters.validate()?;
+    let body: Body = parameters.into();
+
+    client.call_json("set_state", "/api/setstate", &body)
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn body() {
+        let parameters =
+            Parameters::new("a_home")
+                .plug("a_plug", "a_gateway", true)
+                .dimmer("a_dimmer", "a_gateway", 40);
+
+        let body = serde_json::to_string(&Body::from(&parameters)).unwrap();
+
+        assert_that(&body).is_equal_to(
+            r#"{"home":{"id":"a_home","modules":[{"id":"a_plug","bridge":"a_gateway","on":true},{"id":"a_dimmer","bridge":"a_gateway","brightness":40}]}}"#
+                .to_string(),
+        );
+    }
+
+    #[test]
+    fn brightness_out_of_range() {
+        let parameters = Parameters::new("a_home").dimmer("a_dimmer", "a_gateway", 101);
+
+        let res = parameters.validate().map_err(|e| e.kind().clone());
+
+        assert_that(&res).is_err_containing(ErrorKind::InvalidParameter {
+            name: "brightness",
+            reason: "brightness 101 of module 'a_dimmer' exceeds 100".to_string(),
+        });
+    }
+
+    #[test]
+    fn shutter() {
+        let parameters = Parameters::new("a_home").shutter("a_shutter", "a_velux_gateway", 0);
+
+        let body = serde_json::to_string(&Body::from(&parameters)).unwrap();
+
+        assert_that(&body).is_equal_to(
+            r#"{"home":{"id":"a_home","modules":[{"id":"a_shutter","bridge":"a_velux_gateway","target_position":0}]}}"#
+                .to_string(),
+        );
+    }
+
+    #[test]
+    fn target_position_out_of_range() {
+        let parameters = Parameters::new("a_home").shutter("a_shutter", "a_velux_gateway", 150);
+
+        let res = parameters.validate().map_err(|e| e.kind().clone());
+
+        assert_that(&res).is_err_containing(ErrorKind::InvalidParameter {
+            name: "target_position",
+            reason: "target position 150 of module 'a_shutter' exceeds 100".to_string(),
+        });
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{
+    client::{
+        get_homes_data::{Schedule, Timetable},
+        AuthenticatedClient,
+    },
+    errors::Result,
+};
+
+use serde::{Deserialize, Serialize};
+
+pub struct Parameters<'a> {
+    home_id: &'a str,
+    schedule: &'a Schedule,
+}
+
+impl<'a> Parameters<'a> {
+    pub fn new(home_id: &'a str, schedule: &'a Schedule) -> Self {
+        Parameters { home_id, schedule }
+    }
+}
+
+#[derive(Serialize)]
+struct Body<'a> {
+    home_id: &'a str,
+    schedule_id: &'a str,
+    name: &'a str,
+    timetable: &'a [Timetable],
+    zones: Vec<ZoneBody<'a>>,
+    hg_temp: i64,
+    away_temp: i64,
+}
+
+#[derive(Serialize)]
+struct ZoneBody<'a> {
+    id: i64,
+    name: &'a str,
+    #[serde(rename = "type")]
+    type_field: i64,
+    rooms: Vec<RoomBody<'a>>,
+}
+
+#[derive(Serialize)]
+struct RoomBody<'a> {
+    id: &'a str,
+    therm_setpoint_temperature: f64,
+}
+
+impl<'a> From<&'a Parameters<'a>> for Body<'a> {
+    fn from(p: &'a Parameters) -> Body<'a> {
+        let zones = p
+            .schedule
+            .zones
+            .iter()
+            .map(|z| {
+                let rooms = z
+                    .room_setpoints()
+                    .iter()
+                    .filter_map(|r| {
+                        r.setpoint.map(|therm_setpoint_temperature| RoomBody {
+                            id: &r.id,
+                            therm_setpoint_temperature,
+                        })
+                    })
+                    .collect();
+                ZoneBody {
+                    id: z.id,
+                    name: &z.name,
+                    type_field: z.type_field.into(),
+                    rooms,
+                }
+            })
+            .collect();
+
+        Body {
+            home_id: p.home_id,
+            schedule_id: &p.schedule.id,
+            name: &p.schedule.name,
+            timetable: &p.schedule.timetable,
+            zones,
+            hg_temp: p.schedule.hg_temp,
+            away_temp: p.schedule.away_temp,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Response {
+    pub status: String,
+    pub time_server: usize,
+}
+
+/// Replaces the schedule with the id of `schedule` after validating it, cf. `Schedule::validate`
+// cf. https://dev.netatmo.com/apidocumentation/energy#synchomeschedule
+pub fn sync_home_schedule(client: &AuthenticatedClient, parameters: &Parameters) -> Result<Response> {
+    parameters.schedule.validate()?;
+    let body: Body = parameters.into();
+
+    client.call_json("sync_home_schedule", "/api/synchomeschedule", &body)
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+    use crate::{
+        client::transport::{replay_transcript, TranscriptEntry},
+        errors::ErrorKind,
+    };
+
+    fn schedule() -> Schedule {
+        let json = r#"{
+  "timetable": [{"zone_id": 1, "m_offset": 0}, {"zone_id": 0, "m_offset": 420}, {"zone_id": 1, "m_offset": 1320}],
+  "zones": [
+    {"name": "Comfort", "id": 0, "type": 0, "rooms_temp": [{"room_id": "1234", "temp": 21}]},
+    {"name": "Night", "id": 1, "type": 1, "rooms": [{"id": "1234", "therm_setpoint_temperature": 17}, {"id": "5678"}]}
+  ],
+  "name": "Winter",
+  "default": false,
+  "away_temp": 12,
+  "hg_temp": 7,
+  "id": "a_schedule",
+  "selected": true,
+  "type": "therm"
+}"#;
+
+        serde_json::from_str(json).expect("failed to parse schedule")
+    }
+
+    #[test]
+    fn posts_schedule() {
+        let request_body = r#"{
+  "home_id": "a_home",
+  "schedule_id": "a_schedule",
+  "name": "Winter",
+  "timetable": [{"zone_id": 1, "m_offset": 0}, {"zone_id": 0, "m_offset": 420}, {"zone_id": 1, "m_offset": 1320}],
+  "zones": [
+    {"id": 0, "name": "Comfort", "type": 0, "rooms": [{"id": "1234", "therm_setpoint_temperature": 21.0}]},
+    {"id": 1, "name": "Night", "type": 1, "rooms": [{"id": "1234", "therm_setpoint_temperature": 17.0}]}
+  ],
+  "hg_temp": 7,
+  "away_temp": 12
+}"#;
+        let client = replay_transcript(
+            "sync-home-schedule",
+            &[TranscriptEntry {
+                request_body: Some(request_body.to_string()),
+                ..TranscriptEntry::response(
+                    "sync_home_schedule",
+                    200,
+                    r#"{"status": "ok", "time_server": 1556451492}"#,
+                )
+            }],
+        );
+        let schedule = schedule();
+
+        let res = sync_home_schedule(&client, &Parameters::new("a_home", &schedule));
+
+        assert_that(&res.map(|x| x.status)).is_ok_containing("ok".to_string());
+    }
+
+    #[test]
+    fn posting_another_schedule_mismatches_transcript() {
+        let client = replay_transcript(
+            "sync-home-schedule-mismatch",
+            &[TranscriptEntry {
+                request_body: Some(r#"{"home_id": "another_home"}"#.to_string()),
+                ..TranscriptEntry::response(
+                    "sync_home_schedule",
+                    200,
+                    r#"{"status": "ok", "time_server": 1556451492}"#,
+                )
+            }],
+        );
+        let schedule = schedule();
+
+        let res = sync_home_schedule(&client, &Parameters::new("a_home", &schedule)).map_err(|e| e.kind().clone());
+
+        assert_that(&res).is_err_containing(ErrorKind::TranscriptMismatch {
+            name: "sync_home_schedule",
+        });
+    }
+
+    #[test]
+    fn invalid_schedule_is_not_sent() {
+        // Nothing to replay, so any request sent would fail with `TranscriptExhausted`
+        let client = replay_transcript("sync-home-schedule-invalid", &[]);
+        let mut schedule = schedule();
+        schedule.timetable[1].zone_id = 4;
+
+        let res = sync_home_schedule(&client, &Parameters::new("a_home", &schedule)).map_err(|e| e.kind().clone());
+
+        assert_that(&res).is_err_containing(ErrorKind::InvalidSchedule {
+            reason: "timetable entry 1 references undefined zone 4".to_string(),
+        });
+    }
+}
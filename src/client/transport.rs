@@ -0,0 +1,985 @@
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    fs::File,
+    io::{BufRead, BufReader, Write},
+    path::Path,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use log::{trace, warn};
+use reqwest::blocking::{Client, RequestBuilder, Response};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, ETAG, IF_NONE_MATCH, RETRY_AFTER, USER_AGENT};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::errors::{ErrorContext, ErrorKind, Result};
+
+/// Request parameters and response fields that carry credentials and must not end up in a transcript
+const SECRETS: &[&str] = &["access_token", "refresh_token", "client_id", "client_secret"];
+const REDACTED: &str = "<redacted>";
+const NETATMO_URL: &str = "https://api.netatmo.com";
+const DEFAULT_USER_AGENT: &str = concat!("netatmo-rs/", env!("CARGO_PKG_VERSION"));
+/// Netatmo allows 500 requests per user and hour
+const RATE_LIMIT: usize = 500;
+const RATE_LIMIT_WINDOW: Duration = Duration::from_secs(60 * 60);
+
+/// An estimate of the rate limit budget of a client, cf. `AuthenticatedClient::rate_limit_status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitStatus {
+    /// The requests sent within the last hour; equals `limit` while Netatmo rejects requests as rate limited
+    pub used: usize,
+    pub limit: usize,
+    /// The time until the next request drops out of the window or the rate limiting ends; `None` if `used` is 0
+    pub window_reset: Option<Duration>,
+}
+
+impl RateLimitStatus {
+    /// The requests that may be sent before hitting the limit
+    pub fn remaining(&self) -> usize {
+        self.limit.saturating_sub(self.used)
+    }
+}
+
+/// The requests sent within the rate limit window and an observed rate limiting
+#[derive(Debug, Default)]
+struct RequestLog {
+    sent: VecDeque<Instant>,
+    rate_limited_until: Option<Instant>,
+}
+
+impl RequestLog {
+    fn record(&mut self, now: Instant) {
+        self.expire(now);
+        self.sent.push_back(now);
+    }
+
+    /// Records a rejection as rate limited; without `retry_after`, rate limiting is assumed to last until the oldest
+    /// request drops out of the window
+    fn record_rate_limited(&mut self, now: Instant, retry_after: Option<Duration>) {
+        let until = match retry_after {
+            Some(retry_after) => now + retry_after,
+            None => self.sent.front().map_or(now, |x| *x + RATE_LIMIT_WINDOW),
+        };
+        self.rate_limited_until = Some(until);
+    }
+
+    fn status(&mut self, now: Instant) -> RateLimitStatus {
+        self.expire(now);
+        if let Some(until) = self.rate_limited_until {
+            return RateLimitStatus {
+                used: RATE_LIMIT,
+                limit: RATE_LIMIT,
+                window_reset: Some(until - now),
+            };
+        }
+
+        RateLimitStatus {
+            used: self.sent.len().min(RATE_LIMIT),
+            limit: RATE_LIMIT,
+            window_reset: self.sent.front().map(|x| (*x + RATE_LIMIT_WINDOW) - now),
+        }
+    }
+
+    fn expire(&mut self, now: Instant) {
+        while matches!(self.sent.front(), Some(x) if *x + RATE_LIMIT_WINDOW <= now) {
+            self.sent.pop_front();
+        }
+        if matches!(self.rate_limited_until, Some(x) if x <= now) {
+            self.rate_limited_until = None;
+        }
+    }
+}
+
+/// A request and its response as recorded in a transcript
+///
+/// A transcript is a file with one JSON serialized entry per line. Credentials are redacted from the parameters and
+/// the response body, so transcripts can be attached to bug reports.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TranscriptEntry {
+    pub endpoint: String,
+    pub params: BTreeMap<String, String>,
+    /// The redacted body of requests posting JSON, e.g. of `set_state`
+    ///
+    /// If set, replaying fails with `ErrorKind::TranscriptMismatch` for requests posting another body.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_body: Option<String>,
+    pub status: u16,
+    pub body: String,
+    /// Whether `body` is the base64 encoding of a body that isn't UTF-8, e.g. a picture
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub binary: bool,
+}
+
+#[cfg(test)]
+impl TranscriptEntry {
+    /// An entry answering a call of `endpoint` with `status` and `body`
+    pub(crate) fn response(endpoint: &str, status: u16, body: &str) -> Self {
+        TranscriptEntry {
+            endpoint: endpoint.to_string(),
+            params: BTreeMap::new(),
+            request_body: None,
+            status,
+            body: body.to_string(),
+            binary: false,
+        }
+    }
+}
+
+/// A client replaying `entries`, e.g. to test API calls against recorded responses
+#[cfg(test)]
+pub(crate) fn replay_transcript(name: &str, entries: &[TranscriptEntry]) -> super::AuthenticatedClient {
+    load_transcript(name, entries, |path: &Path| crate::NetatmoClient::replay(path))
+}
+
+/// A transport replaying `entries`, cf. `replay_transcript`
+#[cfg(test)]
+pub(crate) fn replay_transport(name: &str, entries: &[TranscriptEntry]) -> Transport {
+    load_transcript(name, entries, |path: &Path| Transport::replay_from(path))
+}
+
+/// Writes `entries` to a temporary transcript named after `name` and loads it by `load`
+///
+/// The transcript is removed when loading returns, even if it panics.
+#[cfg(test)]
+fn load_transcript<T>(name: &str, entries: &[TranscriptEntry], load: impl FnOnce(&Path) -> Result<T>) -> T {
+    struct RemoveOnDrop(std::path::PathBuf);
+    impl Drop for RemoveOnDrop {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    let path = RemoveOnDrop(std::env::temp_dir().join(format!("netatmo-rs-{}-{}.jsonl", name, std::process::id())));
+    let transcript: Vec<_> = entries
+        .iter()
+        .map(|x| serde_json::to_string(x).expect("failed to serialize transcript entry"))
+        .collect();
+    std::fs::write(&path.0, transcript.join("\n")).expect("failed to write transcript");
+
+    load(&path.0).expect("failed to load transcript")
+}
+
+/// The hosts a client sends its requests to, e.g. to route them through a proxy or to a test server
+///
+/// OAuth requests go to `oauth`, all other API calls to `api`; both default to Netatmo's host. URLs returned by
+/// Netatmo, like those of event pictures, are used as they are.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BaseUrls {
+    api: String,
+    oauth: String,
+}
+
+impl BaseUrls {
+    /// Fails with `ErrorKind::InvalidParameter` unless both are absolute HTTP or HTTPS URLs
+    pub fn new(api: &str, oauth: &str) -> Result<Self> {
+        Ok(BaseUrls {
+            api: base_url("api", api)?,
+            oauth: base_url("oauth", oauth)?,
+        })
+    }
+
+    /// Sends both API and OAuth requests to `url`
+    pub fn single(url: &str) -> Result<Self> {
+        BaseUrls::new(url, url)
+    }
+
+    pub fn api(&self) -> &str {
+        &self.api
+    }
+
+    pub fn oauth(&self) -> &str {
+        &self.oauth
+    }
+
+    pub(crate) fn api_url(&self, path: &str) -> String {
+        format!("{}{}", self.api, path)
+    }
+
+    pub(crate) fn oauth_url(&self, path: &str) -> String {
+        format!("{}{}", self.oauth, path)
+    }
+}
+
+impl Default for BaseUrls {
+    fn default() -> Self {
+        BaseUrls {
+            api: NETATMO_URL.to_string(),
+            oauth: NETATMO_URL.to_string(),
+        }
+    }
+}
+
+fn base_url(name: &'static str, url: &str) -> Result<String> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => {
+            Ok(url.trim_end_matches('/').to_string())
+        }
+        _ => Err(ErrorKind::InvalidParameter {
+            name,
+            reason: format!("'{}' is not an absolute HTTP or HTTPS URL", url),
+        }
+        .into()),
+    }
+}
+
+/// How a client retries requests failing transiently, i.e. because of a network error or a server error (5xx)
+///
+/// The delay doubles with every retry, starting at `initial_delay` up to `max_delay`. Requests rejected by Netatmo, e.g.
+/// because of an invalid grant, are never retried. Neither are requests changing the state of devices, like
+/// `set_room_thermpoint`, as a server error doesn't tell whether the change has been applied already.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl RetryPolicy {
+    /// A policy failing on the first error
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_retries: 0,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// The delay before retry number `retry` counting from 0
+    fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.checked_pow(retry).unwrap_or(u32::MAX);
+        self.initial_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |x| x.min(self.max_delay))
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 3,
+            initial_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Sends the requests of a client and optionally records them to or replays them from a transcript
+#[derive(Debug)]
+pub(crate) struct Transport {
+    http: Client,
+    user_agent: String,
+    recorder: Option<Mutex<File>>,
+    replay: Option<Mutex<VecDeque<TranscriptEntry>>>,
+    requests: Mutex<RequestLog>,
+    retry_policy: RetryPolicy,
+    base_urls: BaseUrls,
+    /// The responses carrying an ETag by request, if conditional requests are enabled
+    etags: Option<Mutex<HashMap<String, CachedResponse>>>,
+}
+
+/// A response kept to answer a request from when the server reports it as not modified
+#[derive(Debug)]
+struct CachedResponse {
+    etag: String,
+    headers: HeaderMap,
+    body: Vec<u8>,
+}
+
+impl Transport {
+    pub(crate) fn new() -> Self {
+        Transport {
+            http: Client::new(),
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            recorder: None,
+            replay: None,
+            requests: Mutex::new(RequestLog::default()),
+            retry_policy: RetryPolicy::default(),
+            base_urls: BaseUrls::default(),
+            etags: None,
+        }
+    }
+
+    /// A transport sharing the HTTP client and User-Agent, but neither transcript nor request log, e.g. for a
+    /// background thread
+    pub(crate) fn detached(&self) -> Self {
+        Transport {
+            http: self.http.clone(),
+            user_agent: self.user_agent.clone(),
+            retry_policy: self.retry_policy,
+            base_urls: self.base_urls.clone(),
+            ..Transport::new()
+        }
+    }
+
+    pub(crate) fn post(&self, url: &str) -> RequestBuilder {
+        self.http.post(url).header(USER_AGENT, &self.user_agent)
+    }
+
+    pub(crate) fn get(&self, url: &str) -> RequestBuilder {
+        self.http.get(url).header(USER_AGENT, &self.user_agent)
+    }
+
+    pub(crate) fn user_agent(self, user_agent: &str) -> Self {
+        Transport {
+            user_agent: user_agent.to_string(),
+            ..self
+        }
+    }
+
+    pub(crate) fn retry_policy(self, retry_policy: RetryPolicy) -> Self {
+        Transport { retry_policy, ..self }
+    }
+
+    pub(crate) fn base_urls(&self) -> &BaseUrls {
+        &self.base_urls
+    }
+
+    pub(crate) fn with_base_urls(self, base_urls: BaseUrls) -> Self {
+        Transport { base_urls, ..self }
+    }
+
+    pub(crate) fn conditional_requests(self) -> Self {
+        Transport {
+            etags: Some(Mutex::new(HashMap::new())),
+            ..self
+        }
+    }
+
+    pub(crate) fn record_to<P: AsRef<Path>>(self, path: P) -> Result<Self> {
+        let file = File::create(path).map_err(|e| e.context(ErrorKind::TranscriptAccessFailed))?;
+        Ok(Transport {
+            recorder: Some(Mutex::new(file)),
+            ..self
+        })
+    }
+
+    pub(crate) fn replay_from<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let file = File::open(path).map_err(|e| e.context(ErrorKind::TranscriptAccessFailed))?;
+        let mut entries = VecDeque::new();
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| e.context(ErrorKind::TranscriptAccessFailed))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let entry: TranscriptEntry =
+                serde_json::from_str(&line).map_err(|e| e.context(ErrorKind::JsonDeserializationFailed))?;
+            entries.push_back(entry);
+        }
+
+        Ok(Transport {
+            replay: Some(Mutex::new(entries)),
+            ..Transport::new()
+        })
+    }
+
+    /// Sends a request like `send`, but conditionally on the ETag of the previous response to the same request
+    ///
+    /// If the server reports the response as not modified, the previous response is returned. Servers ignoring
+    /// `If-None-Match` simply answer with the full response.
+    pub(crate) fn send_cached(
+        &self,
+        name: &'static str,
+        request: RequestBuilder,
+        params: &HashMap<&str, &str>,
+    ) -> Result<Response> {
+        let etags = match self.etags {
+            Some(ref etags) => etags,
+            None => return self.send(name, request, params),
+        };
+        let key = cache_key(name, params);
+        let etag = etags
+            .lock()
+            .expect("ETag cache lock poisoned")
+            .get(&key)
+            .map(|x| x.etag.clone());
+        let request = match etag {
+            Some(etag) => request.header(IF_NONE_MATCH, etag),
+            None => request,
+        };
+
+        let res = self.send(name, request, params)?;
+        revalidate(name, etags, key, res)
+    }
+
+    /// Sends a request, retrying it according to the retry policy if it fails transiently
+    pub(crate) fn send(
+        &self,
+        name: &'static str,
+        mut request: RequestBuilder,
+        params: &HashMap<&str, &str>,
+    ) -> Result<Response> {
+        let mut retry = 0;
+        loop {
+            // A request with a streamed body can't be cloned and thus not be retried
+            let next = request.try_clone();
+            let res = self.send_once(name, request, params);
+            let failure = match transient_failure(&res) {
+                Some(failure) => failure,
+                None => return res,
+            };
+            match next {
+                Some(next) if retry < self.retry_policy.max_retries => {
+                    let delay = self.retry_policy.delay(retry);
+                    retry += 1;
+                    warn!(
+                        "Retrying '{}' ({}/{}) in {:?} after {}",
+                        name, retry, self.retry_policy.max_retries, delay, failure
+                    );
+                    // Replayed responses arrive immediately, so there is nothing to wait for
+                    if self.replay.is_none() {
+                        thread::sleep(delay);
+                    }
+                    request = next;
+                }
+                _ => {
+                    if retry > 0 {
+                        warn!("Giving up on '{}' after {} retries: {}", name, retry, failure);
+                    }
+                    return res;
+                }
+            }
+        }
+    }
+
+    /// Sends a request changing state on the server exactly once, as retrying it could apply the change twice
+    pub(crate) fn send_write(
+        &self,
+        name: &'static str,
+        request: RequestBuilder,
+        params: &HashMap<&str, &str>,
+    ) -> Result<Response> {
+        self.send_once(name, request, params)
+    }
+
+    fn send_once(&self, name: &'static str, request: RequestBuilder, params: &HashMap<&str, &str>) -> Result<Response> {
+        let request_id = next_request_id();
+        let tagged = |res: Result<Response>| {
+            res.map(|mut res| {
+                if let Ok(value) = HeaderValue::from_str(&request_id) {
+                    res.headers_mut().insert(REQUEST_ID, value);
+                }
+                res
+            })
+            .map_err(|e| e.with_request_id(Some(request_id.clone())))
+        };
+
+        if let Some(ref replay) = self.replay {
+            trace!("Replaying request {} for '{}'", request_id, name);
+            return tagged(replay_response(name, &request, replay));
+        }
+
+        let request_body = match self.recorder {
+            Some(_) => json_body(&request),
+            None => None,
+        };
+        trace!("Sending request {} for '{}'", request_id, name);
+        let res = request
+            .header(REQUEST_ID, &request_id)
+            .send()
+            .map_err(|e| e.context(ErrorKind::FailedToSendRequest));
+        let res = tagged(res)?;
+        self.record_request(&res);
+        match self.recorder {
+            Some(ref recorder) => tagged(record_response(name, params, request_body, res, recorder)),
+            None => Ok(res),
+        }
+    }
+
+    pub(crate) fn rate_limit_status(&self) -> RateLimitStatus {
+        self.requests
+            .lock()
+            .expect("request log lock poisoned")
+            .status(Instant::now())
+    }
+
+    fn record_request(&self, res: &Response) {
+        let now = Instant::now();
+        let mut requests = self.requests.lock().expect("request log lock poisoned");
+        requests.record(now);
+        if res.status() == StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = retry_after(res).map(Duration::from_secs);
+            requests.record_rate_limited(now, retry_after);
+        }
+    }
+}
+
+/// Identifies identical requests independently of the order of `params`; the access token is left out as it changes
+/// with every refresh
+///
+/// Separators in keys and values are escaped, so different parameters never share a key.
+pub(crate) fn cache_key(name: &str, params: &HashMap<&str, &str>) -> String {
+    let escape = |x: &str| x.replace('%', "%25").replace('&', "%26").replace('=', "%3D");
+    let params: BTreeMap<_, _> = params.iter().filter(|(k, _)| **k != "access_token").collect();
+    let params: Vec<_> = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", escape(k), escape(v)))
+        .collect();
+    format!("{}?{}", name, params.join("&"))
+}
+
+/// Answers a not modified response from the cache and caches responses carrying an ETag
+fn revalidate(
+    name: &'static str,
+    etags: &Mutex<HashMap<String, CachedResponse>>,
+    key: String,
+    res: Response,
+) -> Result<Response> {
+    let mut etags = etags.lock().expect("ETag cache lock poisoned");
+    match res.status() {
+        StatusCode::NOT_MODIFIED => match etags.get(&key) {
+            Some(cached) => {
+                trace!("Response for '{}' not modified", name);
+                let mut headers = cached.headers.clone();
+                if let Some(request_id) = res.headers().get(REQUEST_ID) {
+                    headers.insert(REQUEST_ID, request_id.clone());
+                }
+                let mut response = http::Response::new(cached.body.clone());
+                *response.headers_mut() = headers;
+                Ok(Response::from(response))
+            }
+            None => Ok(res),
+        },
+        StatusCode::OK => {
+            let etag = match res.headers().get(ETAG).and_then(|x| x.to_str().ok()) {
+                Some(etag) => etag.to_string(),
+                None => return Ok(res),
+            };
+            let headers = res.headers().clone();
+            let body = res
+                .bytes()
+                .map_err(|e| e.context(ErrorKind::FailedToReadResponse))?
+                .to_vec();
+            let mut response = http::Response::new(body.clone());
+            *response.headers_mut() = headers.clone();
+            etags.insert(key, CachedResponse { etag, headers, body });
+            Ok(Response::from(response))
+        }
+        _ => Ok(res),
+    }
+}
+
+/// Describes why a request failed if it failed because of the network or the server, so that sending it again may
+/// succeed
+fn transient_failure(res: &Result<Response>) -> Option<String> {
+    match res {
+        Ok(res) if res.status().is_server_error() => Some(format!(
+            "status {} (request id {})",
+            res.status().as_u16(),
+            request_id(res).as_deref().unwrap_or("-")
+        )),
+        Err(e) if *e.kind() == ErrorKind::FailedToSendRequest => Some(e.to_string()),
+        _ => None,
+    }
+}
+
+/// The header carrying the id that correlates a request with Netatmo's server-side logs
+pub(crate) const REQUEST_ID: &str = "x-request-id";
+
+/// Generates a short id unique within this process and unlikely to repeat across processes
+fn next_request_id() -> String {
+    static COUNTER: AtomicU32 = AtomicU32::new(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.subsec_nanos())
+        .unwrap_or_default();
+    format!("{:08x}{:04x}", nanos ^ std::process::id(), count & 0xffff)
+}
+
+/// The id `Transport::send` tagged the response with
+pub(crate) fn request_id(res: &Response) -> Option<String> {
+    res.headers()
+        .get(REQUEST_ID)
+        .and_then(|x| x.to_str().ok())
+        .map(str::to_string)
+}
+
+/// The seconds to wait before retrying as requested by the `Retry-After` header, given either as seconds or as
+/// HTTP-date; dates in the past yield 0
+pub(crate) fn retry_after(res: &Response) -> Option<u64> {
+    let value = res.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+    match value.parse() {
+        Ok(secs) => Some(secs),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            let wait = date.duration_since(SystemTime::now()).unwrap_or_default();
+            Some(wait.as_secs())
+        }
+    }
+}
+
+fn replay_response(
+    name: &'static str,
+    request: &RequestBuilder,
+    replay: &Mutex<VecDeque<TranscriptEntry>>,
+) -> Result<Response> {
+    let mut entries = replay.lock().expect("transcript lock poisoned");
+    let position = entries
+        .iter()
+        .position(|x| x.endpoint == name)
+        .ok_or(ErrorKind::TranscriptExhausted { name })?;
+    let entry = entries.remove(position).expect("position of existing entry");
+    if let Some(ref recorded) = entry.request_body {
+        let sent = json_body(request).and_then(|x| serde_json::from_str::<Value>(&x).ok());
+        if sent.is_none() || sent != serde_json::from_str::<Value>(recorded).ok() {
+            return Err(ErrorKind::TranscriptMismatch { name }.into());
+        }
+    }
+    trace!("Replaying ({}) response for '{}'", entry.status, name);
+
+    let status = http::StatusCode::from_u16(entry.status).map_err(|_| ErrorKind::UnknownApiCallFailure {
+        name,
+        status_code: entry.status,
+    })?;
+    let body = if entry.binary {
+        BASE64
+            .decode(&entry.body)
+            .map_err(|e| e.context(ErrorKind::JsonDeserializationFailed))?
+    } else {
+        entry.body.into_bytes()
+    };
+    let mut response = http::Response::new(body);
+    *response.status_mut() = status;
+
+    Ok(Response::from(response))
+}
+
+/// The redacted body of `request` if it posts JSON
+fn json_body(request: &RequestBuilder) -> Option<String> {
+    let request = request.try_clone()?.build().ok()?;
+    if !matches!(request.headers().get(CONTENT_TYPE), Some(x) if x.as_bytes().starts_with(b"application/json")) {
+        return None;
+    }
+    let body = std::str::from_utf8(request.body()?.as_bytes()?).ok()?;
+
+    Some(redact_body(body))
+}
+
+fn record_response(
+    name: &'static str,
+    params: &HashMap<&str, &str>,
+    request_body: Option<String>,
+    res: Response,
+    recorder: &Mutex<File>,
+) -> Result<Response> {
+    let status = res.status();
+    let headers = res.headers().clone();
+    let body = res.bytes().map_err(|e| e.context(ErrorKind::FailedToReadResponse))?;
+
+    let params = params
+        .iter()
+        .map(|(k, v)| {
+            let v = if SECRETS.contains(k) { REDACTED } else { v };
+            (k.to_string(), v.to_string())
+        })
+        .collect();
+    let entry = TranscriptEntry {
+        endpoint: name.to_string(),
+        params,
+        request_body,
+        status: status.as_u16(),
+        body: match std::str::from_utf8(&body) {
+            Ok(text) => redact_body(text),
+            Err(_) => BASE64.encode(&body),
+        },
+        binary: std::str::from_utf8(&body).is_err(),
+    };
+    let line = serde_json::to_string(&entry).map_err(|e| e.context(ErrorKind::JsonSerializationFailed))?;
+    {
+        let mut file = recorder.lock().expect("transcript lock poisoned");
+        writeln!(file, "{}", line).map_err(|e| e.context(ErrorKind::TranscriptAccessFailed))?;
+    }
+
+    let mut response = http::Response::new(body);
+    *response.status_mut() = status;
+    *response.headers_mut() = headers;
+
+    Ok(Response::from(response))
+}
+
+fn redact_body(body: &str) -> String {
+    fn redact(value: &mut Value) {
+        match value {
+            Value::Object(map) => {
+                for (k, v) in map.iter_mut() {
+                    if SECRETS.contains(&k.as_str()) {
+                        *v = Value::String(REDACTED.to_string());
+                    } else {
+                        redact(v);
+                    }
+                }
+            }
+            Value::Array(values) => values.iter_mut().for_each(redact),
+            _ => {}
+        }
+    }
+
+    match serde_json::from_str::<Value>(body) {
+        Ok(mut value) => {
+            redact(&mut value);
+            value.to_string()
+        }
+        Err(_) => body.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    use super::*;
+    use crate::{set_room_thermpoint, Netatmo, NetatmoClient};
+
+    #[test]
+    fn replay_transcript() {
+        let path = std::env::temp_dir().join(format!("netatmo-rs-transcript-{}.jsonl", std::process::id()));
+        let entry = TranscriptEntry {
+            endpoint: "set_room_thermpoint".to_string(),
+            params: BTreeMap::new(),
+            request_body: None,
+            status: 200,
+            body: r#"{"status":"ok","time_server":1556451492}"#.to_string(),
+            binary: false,
+        };
+        std::fs::write(&path, serde_json::to_string(&entry).unwrap()).unwrap();
+        let client = NetatmoClient::replay(&path).expect("failed to load transcript");
+        let parameters = set_room_thermpoint::Parameters::new("a_home", "a_room", set_room_thermpoint::Mode::Home);
+
+        let first = client.set_room_thermpoint(&parameters);
+        let second = client.set_room_thermpoint(&parameters);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_that(&first.map(|x| x.time_server)).is_ok_containing(1_556_451_492);
+        assert_that(&second.map_err(|e| e.kind().clone())).is_err_containing(ErrorKind::TranscriptExhausted {
+            name: "set_room_thermpoint",
+        });
+    }
+
+    #[test]
+    fn errors_carry_request_id() {
+        let path = std::env::temp_dir().join(format!("netatmo-rs-request-id-{}.jsonl", std::process::id()));
+        let entry = TranscriptEntry {
+            endpoint: "set_room_thermpoint".to_string(),
+            params: BTreeMap::new(),
+            request_body: None,
+            status: 400,
+            body: r#"{"error":{"code":21,"message":"Invalid room id"}}"#.to_string(),
+            binary: false,
+        };
+        std::fs::write(&path, serde_json::to_string(&entry).unwrap()).unwrap();
+        let client = NetatmoClient::replay(&path).expect("failed to load transcript");
+        let parameters = set_room_thermpoint::Parameters::new("a_home", "a_room", set_room_thermpoint::Mode::Home);
+
+        let err = client.set_room_thermpoint(&parameters).expect_err("API call succeeded");
+        std::fs::remove_file(&path).unwrap();
+
+        let request_id = err.request_id().expect("error without request id").to_string();
+        assert_that(&request_id.len()).is_equal_to(12);
+        assert_that(&err.to_string()).ends_with(format!("(request id {})", request_id).as_str());
+    }
+
+    #[test]
+    fn retry_delay_doubles_up_to_max_delay() {
+        let policy = RetryPolicy::default();
+
+        let delays: Vec<_> = (0..7).map(|x| policy.delay(x).as_secs()).collect();
+
+        assert_that(&delays).is_equal_to(vec![1, 2, 4, 8, 16, 30, 30]);
+        assert_that(&policy.delay(u32::MAX)).is_equal_to(policy.max_delay);
+    }
+
+    #[test]
+    fn cache_key_ignores_access_token_and_order() {
+        let mut first = HashMap::new();
+        first.insert("device_id", "a_device");
+        first.insert("get_favorites", "false");
+        first.insert("access_token", "access|1");
+        let mut second = first.clone();
+        second.insert("access_token", "access|2");
+
+        assert_that(&cache_key("get_station_data", &first))
+            .is_equal_to("get_station_data?device_id=a_device&get_favorites=false".to_string());
+        assert_that(&cache_key("get_station_data", &first)).is_equal_to(cache_key("get_station_data", &second));
+    }
+
+    #[test]
+    fn cache_key_escapes_separators() {
+        let mut first = HashMap::new();
+        first.insert("device_id", "a&module_id=b");
+        let mut second = HashMap::new();
+        second.insert("device_id", "a");
+        second.insert("module_id", "b");
+
+        assert_that(&cache_key("get_measure", &first))
+            .is_equal_to("get_measure?device_id=a%26module_id%3Db".to_string());
+        assert_that(&cache_key("get_measure", &first)).is_not_equal_to(cache_key("get_measure", &second));
+    }
+
+    #[test]
+    fn revalidate_answers_not_modified_from_cache() {
+        let response = |status, etag: Option<&str>, body: &str| {
+            let mut builder = http::Response::builder().status(status);
+            if let Some(etag) = etag {
+                builder = builder.header(ETAG, etag);
+            }
+            Response::from(builder.body(body.to_string()).unwrap())
+        };
+        let etags = Mutex::new(HashMap::new());
+        let key = || "get_station_data?".to_string();
+
+        let fresh = revalidate("get_station_data", &etags, key(), response(200, Some("\"v1\""), "{}"));
+        let not_modified = revalidate("get_station_data", &etags, key(), response(304, None, ""));
+        let ignored = revalidate(
+            "get_station_data",
+            &etags,
+            "other?".to_string(),
+            response(200, None, "[]"),
+        );
+
+        assert_that(&fresh.unwrap().text().unwrap()).is_equal_to("{}".to_string());
+        let not_modified = not_modified.unwrap();
+        assert_that(&not_modified.status()).is_equal_to(StatusCode::OK);
+        assert_that(&not_modified.text().unwrap()).is_equal_to("{}".to_string());
+        assert_that(&ignored.unwrap().text().unwrap()).is_equal_to("[]".to_string());
+        assert_that(&etags.lock().unwrap().len()).is_equal_to(1);
+    }
+
+    #[test]
+    fn base_urls() {
+        let urls = BaseUrls::new("http://localhost:8080/", "https://proxy.example.com/netatmo").unwrap();
+
+        assert_that(&urls.api_url("/api/homesdata")).is_equal_to("http://localhost:8080/api/homesdata".to_string());
+        assert_that(&urls.oauth_url("/oauth2/token"))
+            .is_equal_to("https://proxy.example.com/netatmo/oauth2/token".to_string());
+        assert_that(&BaseUrls::single("http://localhost:8080").unwrap().oauth()).is_equal_to("http://localhost:8080");
+        assert_that(&BaseUrls::default().api_url("/api/getmeasure"))
+            .is_equal_to("https://api.netatmo.com/api/getmeasure".to_string());
+    }
+
+    #[test]
+    fn base_urls_must_be_absolute() {
+        let res = BaseUrls::new("https://api.netatmo.com", "localhost:8080").map_err(|e| e.kind().clone());
+
+        assert_that(&res).is_err_containing(ErrorKind::InvalidParameter {
+            name: "oauth",
+            reason: "'localhost:8080' is not an absolute HTTP or HTTPS URL".to_string(),
+        });
+    }
+
+    #[test]
+    fn transient_failures() {
+        let response = |status| {
+            let response = http::Response::builder()
+                .status(status)
+                .header(REQUEST_ID, "0123abcd0001")
+                .body(String::new())
+                .unwrap();
+            Ok(Response::from(response))
+        };
+
+        assert_that(&transient_failure(&response(502)))
+            .is_equal_to(Some("status 502 (request id 0123abcd0001)".to_string()));
+        assert_that(&transient_failure(&response(400))).is_none();
+        assert_that(&transient_failure(&Err(ErrorKind::FailedToSendRequest.into())))
+            .is_equal_to(Some("failed to send request".to_string()));
+    }
+
+    #[test]
+    fn request_ids_differ() {
+        assert_that(&next_request_id()).is_not_equal_to(next_request_id());
+    }
+
+    #[test]
+    fn rate_limit_status_counts_requests_within_window() {
+        let start = Instant::now();
+        let mut requests = RequestLog::default();
+        requests.record(start);
+        requests.record(start + Duration::from_secs(600));
+
+        let status = requests.status(start + Duration::from_secs(1200));
+        let later = requests.status(start + RATE_LIMIT_WINDOW + Duration::from_secs(1));
+
+        assert_that(&status).is_equal_to(RateLimitStatus {
+            used: 2,
+            limit: RATE_LIMIT,
+            window_reset: Some(Duration::from_secs(2400)),
+        });
+        assert_that(&later.used).is_equal_to(1);
+        assert_that(&later.remaining()).is_equal_to(RATE_LIMIT - 1);
+    }
+
+    #[test]
+    fn rate_limit_status_while_rate_limited() {
+        let start = Instant::now();
+        let mut requests = RequestLog::default();
+        requests.record(start);
+        requests.record_rate_limited(start, Some(Duration::from_secs(120)));
+
+        let limited = requests.status(start + Duration::from_secs(60));
+        let recovered = requests.status(start + Duration::from_secs(120));
+
+        assert_that(&limited.remaining()).is_equal_to(0);
+        assert_that(&limited.window_reset).is_equal_to(Some(Duration::from_secs(60)));
+        assert_that(&recovered.used).is_equal_to(1);
+    }
+
+    #[test]
+    fn record_binary_response() {
+        let path = std::env::temp_dir().join(format!("netatmo-rs-binary-{}.jsonl", std::process::id()));
+        let jpeg = vec![0xff, 0xd8, 0xff, 0xe0, 0x00, 0x10, b'J', b'F', b'I', b'F'];
+        let recorder = Mutex::new(File::create(&path).unwrap());
+
+        let res = record_response(
+            "get_camera_picture",
+            &HashMap::new(),
+            None,
+            Response::from(http::Response::new(jpeg.clone())),
+            &recorder,
+        )
+        .expect("failed to record response");
+        let recorded = res.bytes().unwrap().to_vec();
+        let entry: TranscriptEntry = serde_json::from_str(std::fs::read_to_string(&path).unwrap().trim()).unwrap();
+        let transport = Transport::replay_from(&path).expect("failed to load transcript");
+        std::fs::remove_file(&path).unwrap();
+        let replayed = transport.send("get_camera_picture", transport.get(NETATMO_URL), &HashMap::new());
+
+        assert_that(&recorded).is_equal_to(&jpeg);
+        assert_that(&entry.binary).is_true();
+        assert_that(&entry.body.as_str()).is_equal_to("/9j/4AAQSkZJRg==");
+        assert_that(&replayed.unwrap().bytes().unwrap().to_vec()).is_equal_to(&jpeg);
+    }
+
+    #[test]
+    fn json_body() {
+        let transport = Transport::new();
+        let json = transport
+            .post(NETATMO_URL)
+            .header(CONTENT_TYPE, "application/json")
+            .body(r#"{"home":{"id":"a_home"},"access_token":"secret|1"}"#);
+        let form = transport.post(NETATMO_URL).form(&[("access_token", "secret|1")]);
+
+        assert_that(&super::json_body(&json)).is_equal_to(Some(
+            r#"{"access_token":"<redacted>","home":{"id":"a_home"}}"#.to_string(),
+        ));
+        assert_that(&super::json_body(&form)).is_none();
+    }
+
+    #[test]
+    fn redact_token_response() {
+        let body =
+            r#"{"access_token":"secret|1","expires_in":10800,"refresh_token":"secret|2","scope":["read_station"]}"#;
+
+        let redacted = redact_body(body);
+
+        assert_that(&redacted).is_equal_to(
+            r#"{"access_token":"<redacted>","expires_in":10800,"refresh_token":"<redacted>","scope":["read_station"]}"#
+                .to_string(),
+        );
+    }
+}
@@ -1,12 +1,16 @@
-use std::fmt;
+#![allow(non_local_definitions)]
 
-use failure::{Backtrace, Context, Fail};
+use std::{error::Error as StdError, fmt};
+
+use failure::Fail;
 
 /// The error kind for errors that get returned in the crate
 #[derive(Eq, PartialEq, Debug, Fail)]
 pub enum ErrorKind {
     #[fail(display = "failed to deserialize JSON")]
     JsonDeserializationFailed,
+    #[fail(display = "failed to serialize JSON")]
+    JsonSerializationFailed,
     #[fail(display = "failed to send request")]
     FailedToSendRequest,
     #[fail(display = "failed to read response")]
@@ -24,6 +28,61 @@ pub enum ErrorKind {
         name, status_code
     )]
     UnknownApiCallFailure { name: &'static str, status_code: u16 },
+    #[fail(display = "invalid parameter '{}': {}", name, reason)]
+    InvalidParameter { name: &'static str, reason: String },
+    #[fail(display = "station data contains no dashboard readings")]
+    NoDashboardData,
+    #[fail(display = "newest dashboard reading is {}s old, exceeding {}s", age, max_age)]
+    StaleDashboardData { age: u64, max_age: u64 },
+    #[fail(display = "invalid schedule: {}", reason)]
+    InvalidSchedule { reason: String },
+    #[fail(display = "failed to access token file")]
+    TokenFileAccessFailed,
+    #[fail(display = "stored token is corrupt")]
+    StoredTokenCorrupt,
+    #[fail(display = "failed to access transcript")]
+    TranscriptAccessFailed,
+    #[fail(display = "transcript contains no further response for API call '{}'", name)]
+    TranscriptExhausted { name: &'static str },
+    #[fail(display = "measures of different types cannot be merged")]
+    MeasureTypesMismatch,
+    #[fail(display = "measures of different scales cannot be merged")]
+    MeasureScalesMismatch,
+    #[fail(display = "token endpoint returned an invalid token: {}", detail)]
+    InvalidTokenResponse { detail: String },
+    #[fail(display = "failed to access keyring")]
+    KeyringAccessFailed,
+    #[fail(
+        display = "requested range ends at {} before data is available from {}",
+        date_end, available_from
+    )]
+    DataNotRetained { date_end: usize, available_from: usize },
+    #[fail(
+        display = "API call '{}' failed because the device or module does not exist or is not accessible",
+        name
+    )]
+    DeviceNotFound { name: &'static str },
+    #[fail(display = "client has no client credentials to refresh its token")]
+    MissingClientCredentials,
+    #[fail(display = "refresh token is malformed: {}", reason)]
+    MalformedRefreshToken { reason: String },
+    #[fail(display = "invalid CSV in line {}: {}", line, reason)]
+    InvalidCsv { line: usize, reason: String },
+    #[fail(display = "failed to access CSV")]
+    CsvAccessFailed,
+    #[fail(
+        display = "event '{}' is no longer available, so events after it may be missing",
+        event_id
+    )]
+    EventNotFound { event_id: String },
+    #[fail(display = "API call '{}' posts another body than recorded in the transcript", name)]
+    TranscriptMismatch { name: &'static str },
+    #[fail(display = "API call '{}' failed because of too many requests", name)]
+    RateLimited {
+        name: &'static str,
+        /// Seconds to wait before retrying as requested by the `Retry-After` header, given as seconds or HTTP-date
+        retry_after: Option<u64>,
+    },
 }
 
 impl Clone for ErrorKind {
@@ -31,6 +90,7 @@ impl Clone for ErrorKind {
         use self::ErrorKind::*;
         match *self {
             JsonDeserializationFailed => JsonDeserializationFailed,
+            JsonSerializationFailed => JsonSerializationFailed,
             FailedToSendRequest => FailedToSendRequest,
             FailedToReadResponse => FailedToReadResponse,
             AuthenticationFailed => AuthenticationFailed,
@@ -40,59 +100,153 @@ impl Clone for ErrorKind {
                 msg: msg.clone(),
             },
             UnknownApiCallFailure { name, status_code } => UnknownApiCallFailure { name, status_code },
+            InvalidParameter { name, ref reason } => InvalidParameter {
+                name,
+                reason: reason.clone(),
+            },
+            NoDashboardData => NoDashboardData,
+            StaleDashboardData { age, max_age } => StaleDashboardData { age, max_age },
+            InvalidSchedule { ref reason } => InvalidSchedule { reason: reason.clone() },
+            TokenFileAccessFailed => TokenFileAccessFailed,
+            StoredTokenCorrupt => StoredTokenCorrupt,
+            TranscriptAccessFailed => TranscriptAccessFailed,
+            TranscriptExhausted { name } => TranscriptExhausted { name },
+            MeasureTypesMismatch => MeasureTypesMismatch,
+            MeasureScalesMismatch => MeasureScalesMismatch,
+            InvalidTokenResponse { ref detail } => InvalidTokenResponse { detail: detail.clone() },
+            KeyringAccessFailed => KeyringAccessFailed,
+            DataNotRetained {
+                date_end,
+                available_from,
+            } => DataNotRetained {
+                date_end,
+                available_from,
+            },
+            DeviceNotFound { name } => DeviceNotFound { name },
+            MissingClientCredentials => MissingClientCredentials,
+            MalformedRefreshToken { ref reason } => MalformedRefreshToken { reason: reason.clone() },
+            InvalidCsv { line, ref reason } => InvalidCsv {
+                line,
+                reason: reason.clone(),
+            },
+            CsvAccessFailed => CsvAccessFailed,
+            EventNotFound { ref event_id } => EventNotFound {
+                event_id: event_id.clone(),
+            },
+            TranscriptMismatch { name } => TranscriptMismatch { name },
+            RateLimited { name, retry_after } => RateLimited { name, retry_after },
         }
     }
 }
 
 /// The error type for errors that get returned in the lookup module
+///
+/// The underlying error, e.g. of reqwest or serde, is available by `std::error::Error::source`, so error reporters
+/// can print the whole chain.
 #[derive(Debug)]
 pub struct Error {
-    pub(crate) inner: Context<ErrorKind>,
+    kind: ErrorKind,
+    source: Option<Box<dyn StdError + Send + Sync>>,
+    request_id: Option<String>,
 }
 
 impl Error {
     /// Get the kind of the error
     pub fn kind(&self) -> &ErrorKind {
-        self.inner.get_context()
+        &self.kind
+    }
+
+    /// The id sent as `X-Request-Id` with the API call that failed; quote it when contacting Netatmo's support
+    pub fn request_id(&self) -> Option<&str> {
+        self.request_id.as_deref()
+    }
+
+    pub(crate) fn with_request_id(self, request_id: Option<String>) -> Error {
+        Error {
+            request_id: self.request_id.or(request_id),
+            ..self
+        }
     }
 }
 
+/// Clones the kind and request id, but not the source, which isn't necessarily `Clone`
 impl Clone for Error {
     fn clone(&self) -> Self {
         Error {
-            inner: Context::new(self.inner.get_context().clone()),
+            kind: self.kind.clone(),
+            source: None,
+            request_id: self.request_id.clone(),
         }
     }
 }
 
-impl Fail for Error {
-    fn cause(&self) -> Option<&dyn Fail> {
-        self.inner.cause()
-    }
-
-    fn backtrace(&self) -> Option<&Backtrace> {
-        self.inner.backtrace()
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        self.source.as_ref().map(|x| x.as_ref() as &(dyn StdError + 'static))
     }
 }
 
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        fmt::Display::fmt(&self.inner, f)
+        fmt::Display::fmt(&self.kind, f)?;
+        if let Some(ref request_id) = self.request_id {
+            write!(f, " (request id {})", request_id)?;
+        }
+        Ok(())
     }
 }
 
 impl From<ErrorKind> for Error {
     fn from(kind: ErrorKind) -> Error {
         Error {
-            inner: Context::new(kind),
+            kind,
+            source: None,
+            request_id: None,
         }
     }
 }
 
-impl From<Context<ErrorKind>> for Error {
-    fn from(inner: Context<ErrorKind>) -> Error {
-        Error { inner }
+/// Wraps an underlying error as the source of an `Error` of some kind
+pub(crate) trait ErrorContext {
+    fn context(self, kind: ErrorKind) -> Error;
+}
+
+impl<E> ErrorContext for E
+where
+    E: StdError + Send + Sync + 'static,
+{
+    fn context(self, kind: ErrorKind) -> Error {
+        Error {
+            kind,
+            source: Some(Box::new(self)),
+            request_id: None,
+        }
     }
 }
 
 pub type Result<T> = ::std::result::Result<T, Error>;
+
+#[cfg(test)]
+mod test {
+    use spectral::prelude::*;
+
+    // Not `super::*`, which would bring `failure::Fail::context` into scope, too
+    use super::{ErrorContext, ErrorKind, StdError};
+
+    #[test]
+    fn source_chain() {
+        let json = serde_json::from_str::<u64>("not json").unwrap_err();
+        let parse = json.context(ErrorKind::JsonDeserializationFailed);
+        let call = parse.context(ErrorKind::AuthenticationFailed);
+
+        let chain: Vec<_> = std::iter::successors(Some(&call as &dyn StdError), |x| (*x).source())
+            .map(ToString::to_string)
+            .collect();
+
+        assert_that(&chain).is_equal_to(vec![
+            "failed to authenticate".to_string(),
+            "failed to deserialize JSON".to_string(),
+            "expected ident at line 1 column 2".to_string(),
+        ]);
+    }
+}
@@ -2,7 +2,9 @@ pub mod client;
 pub mod errors;
 
 pub use client::{
-    authenticate::{self, Scope},
-    get_home_status, get_homes_data, get_measure, get_station_data, set_room_thermpoint, ClientCredentials, Netatmo,
-    NetatmoClient,
+    authenticate::{self, Endpoint, Scope},
+    device::{self, Device},
+    get_events, get_home_status, get_homes_data, get_measure, get_station_data,
+    module_type::{self, ModuleType},
+    set_room_thermpoint, set_state, sync_home_schedule, ClientCredentials, Netatmo, NetatmoClient,
 };